@@ -0,0 +1,320 @@
+use crate::{file_name, AppName, ArgInfo, Clap, CliType, Matches};
+use clap::{App, AppSettings, ArgSettings};
+use config::ConfigError;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+pub type ArgMatches = clap::ArgMatches<'static>;
+
+impl From<App<'static, 'static>> for Clap {
+    fn from(app: App<'static, 'static>) -> Clap {
+        Clap::new(app)
+    }
+}
+
+impl Clap {
+    pub fn new(app: App<'static, 'static>) -> Self {
+        Self::from_args(app, env::args_os())
+    }
+
+    pub fn from_args<I>(app: App<'static, 'static>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        Self::from_matches(
+            Self::get_args_types(&app),
+            Self::get_args_info(&app),
+            Some(Self::get_app_name(&app, args.first())),
+            app.get_matches_from(args),
+        )
+    }
+
+    /// Like `from_args`, but renders the help of the app for `Clap::help`.
+    pub fn with_app<I>(app: App<'static, 'static>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let mut help = Vec::new();
+        // Sources must be `Send`, which clap 2 apps are not, so only the help is kept
+        // and there is no `Clap::app` as with clap 3 and 4.
+        let help = app.clone().write_help(&mut help).map(|_| help);
+        Self {
+            help: help
+                .ok()
+                .map(|help| String::from_utf8_lossy(&help).into_owned()),
+            ..Self::from_args(app, args)
+        }
+    }
+
+    /// Like `from_args`, but returns parse errors instead of exiting the process.
+    pub fn try_from_args<I>(app: App<'static, 'static>, args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        Ok(Self::from_matches(
+            Self::get_args_types(&app),
+            Self::get_args_info(&app),
+            Some(Self::get_app_name(&app, args.first())),
+            app.get_matches_from_safe(args)?,
+        ))
+    }
+
+    /// Whether `error` from `try_from_args` only asks to show the help, after `--help`
+    /// or when `ArgRequiredElseHelp` got no args, rather than being a parse error.
+    pub fn is_help_request(error: &clap::Error) -> bool {
+        matches!(
+            error.kind,
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::MissingArgumentOrSubcommand
+        )
+    }
+
+    /// Like `try_from_args`, but applies `configure` to the source first, so missing
+    /// required args are reported by their config keys, e.g. after `rename`.
+    /// Other parse errors are wrapped as they are.
+    pub fn try_from_args_with<I, F>(
+        app: App<'static, 'static>,
+        args: I,
+        configure: F,
+    ) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+        F: FnOnce(Self) -> Self,
+    {
+        fn relax(mut app: App<'static, 'static>) -> App<'static, 'static> {
+            // Only the parser list is cleared, the arg settings still tell which args are required.
+            app.p.required.clear();
+            app.p.subcommands = app.p.subcommands.into_iter().map(relax).collect();
+            app
+        }
+
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let error = match Self::try_from_args(app.clone(), args.clone()) {
+            Ok(clap) => return Ok(configure(clap)),
+            Err(error) if error.kind == clap::ErrorKind::MissingRequiredArgument => error,
+            Err(error) => return Err(ConfigError::Foreign(Box::new(error))),
+        };
+        match Self::try_from_args(relax(app), args) {
+            Ok(clap) => Err(configure(clap).missing_required_error(error)),
+            Err(_) => Err(ConfigError::Foreign(Box::new(error))),
+        }
+    }
+
+    fn get_app_name(app: &App, arg0: Option<&OsString>) -> AppName {
+        // Like clap, the first arg names the binary unless it was set on the app.
+        let arg0 = arg0.filter(|_| !app.p.is_set(AppSettings::NoBinaryName));
+        AppName {
+            name: app.get_name().to_owned(),
+            bin_name: app
+                .get_bin_name()
+                .map(str::to_owned)
+                .or_else(|| file_name(arg0?)),
+        }
+    }
+
+    // Subcommands are walked eagerly, there is no `from_args_lazy` as with clap 3 and 4:
+    // once parsed, clap 2 apps also list the help and version flags they added.
+    fn get_args_types(app: &App) -> HashMap<String, CliType> {
+        fn convert(name: &str, takes_value: bool, multiple: bool) -> (String, CliType) {
+            (name.to_owned(), CliType::new(takes_value, multiple))
+        }
+
+        app.p
+            .subcommands
+            .iter()
+            .map(|app| {
+                (
+                    app.p.meta.name.clone(),
+                    CliType::Subcommand(Self::get_args_types(app)),
+                )
+            })
+            .chain(app.p.opts.iter().map(|opt| {
+                convert(
+                    opt.b.name,
+                    opt.b.settings.is_set(ArgSettings::TakesValue),
+                    opt.b.settings.is_set(ArgSettings::Multiple) || opt.v.val_delim.is_some(),
+                )
+            }))
+            .chain(app.p.flags.iter().map(|flag| {
+                convert(
+                    flag.b.name,
+                    flag.b.settings.is_set(ArgSettings::TakesValue),
+                    flag.b.settings.is_set(ArgSettings::Multiple),
+                )
+            }))
+            .chain(app.p.positionals.iter().map(|(_, pos)| {
+                // Positionals always take a value, clap doesn't set `TakesValue` for them.
+                convert(
+                    pos.b.name,
+                    true,
+                    pos.b.settings.is_set(ArgSettings::Multiple) || pos.v.val_delim.is_some(),
+                )
+            }))
+            .chain(
+                app.p
+                    .groups
+                    .iter()
+                    .filter(|group| !group.multiple)
+                    .map(|group| {
+                        (
+                            group.name.to_owned(),
+                            CliType::Group(group.args.iter().map(|&arg| arg.to_owned()).collect()),
+                        )
+                    }),
+            )
+            .collect()
+    }
+
+    fn get_args_info(app: &App) -> HashMap<String, ArgInfo> {
+        let negate_reqs = app.p.is_set(AppSettings::SubcommandsNegateReqs);
+        // Options and flags share one declaration order, positionals (indexed from 1)
+        // and subcommands follow.
+        let positionals = app.p.opts.len() + app.p.flags.len();
+        let subcommands = positionals + app.p.positionals.len();
+        app.p
+            .subcommands
+            .iter()
+            .enumerate()
+            .map(|(order, app)| {
+                (
+                    app.p.meta.name.clone(),
+                    ArgInfo {
+                        order: subcommands + order,
+                        args: Self::get_args_info(app),
+                        ..ArgInfo::default()
+                    },
+                )
+            })
+            .chain(app.p.opts.iter().map(|opt| {
+                (
+                    opt.b.name.to_owned(),
+                    ArgInfo {
+                        delimiter: opt.v.val_delim,
+                        default_values: opt
+                            .v
+                            .default_val
+                            .iter()
+                            .map(|&value| value.to_owned())
+                            .collect(),
+                        has_default: opt.v.default_val.is_some()
+                            || opt.v.default_vals_ifs.is_some(),
+                        env: opt.v.env.is_some(),
+                        possible_values: opt
+                            .v
+                            .possible_vals
+                            .iter()
+                            .flatten()
+                            .map(|&value| value.to_owned())
+                            .collect(),
+                        short: opt.s.short,
+                        long: opt.s.long.map(str::to_owned),
+                        aliases: visible_aliases(&opt.s.aliases),
+                        hidden: opt.b.settings.is_set(ArgSettings::Hidden),
+                        order: opt.s.unified_ord,
+                        help: opt.b.help.map(str::to_owned),
+                        required: opt.b.settings.is_set(ArgSettings::Required),
+                        subcommands_negate_reqs: negate_reqs,
+                        ..ArgInfo::default()
+                    },
+                )
+            }))
+            .chain(app.p.flags.iter().map(|flag| {
+                (
+                    flag.b.name.to_owned(),
+                    ArgInfo {
+                        short: flag.s.short,
+                        long: flag.s.long.map(str::to_owned),
+                        aliases: visible_aliases(&flag.s.aliases),
+                        hidden: flag.b.settings.is_set(ArgSettings::Hidden),
+                        order: flag.s.unified_ord,
+                        help: flag.b.help.map(str::to_owned),
+                        required: flag.b.settings.is_set(ArgSettings::Required),
+                        subcommands_negate_reqs: negate_reqs,
+                        ..ArgInfo::default()
+                    },
+                )
+            }))
+            .chain(app.p.positionals.iter().map(|(index, pos)| {
+                (
+                    pos.b.name.to_owned(),
+                    ArgInfo {
+                        index: Some(index),
+                        delimiter: pos.v.val_delim,
+                        default_values: pos
+                            .v
+                            .default_val
+                            .iter()
+                            .map(|&value| value.to_owned())
+                            .collect(),
+                        has_default: pos.v.default_val.is_some()
+                            || pos.v.default_vals_ifs.is_some(),
+                        env: pos.v.env.is_some(),
+                        possible_values: pos
+                            .v
+                            .possible_vals
+                            .iter()
+                            .flatten()
+                            .map(|&value| value.to_owned())
+                            .collect(),
+                        hidden: pos.b.settings.is_set(ArgSettings::Hidden),
+                        order: positionals + index - 1,
+                        help: pos.b.help.map(str::to_owned),
+                        required: pos.b.settings.is_set(ArgSettings::Required),
+                        subcommands_negate_reqs: negate_reqs,
+                        ..ArgInfo::default()
+                    },
+                )
+            }))
+            .collect()
+    }
+}
+
+fn visible_aliases(aliases: &Option<Vec<(&str, bool)>>) -> Vec<String> {
+    aliases
+        .iter()
+        .flatten()
+        .filter(|(_, visible)| *visible)
+        .map(|(alias, _)| (*alias).to_owned())
+        .collect()
+}
+
+impl Matches for ArgMatches {
+    fn raw_values(&self, name: &str) -> Option<Vec<&OsStr>> {
+        self.values_of_os(name).map(Iterator::collect)
+    }
+
+    fn occurrences(&self, name: &str) -> u64 {
+        self.occurrences_of(name)
+    }
+
+    fn is_default(&self, _name: &str) -> Option<bool> {
+        None
+    }
+
+    fn is_env(&self, _name: &str) -> Option<bool> {
+        None
+    }
+
+    fn count(&self, name: &str) -> u64 {
+        self.occurrences_of(name)
+    }
+
+    fn flag(&self, name: &str) -> Option<bool> {
+        self.is_present(name).then_some(true)
+    }
+
+    fn flag_default(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn indices(&self, name: &str) -> Vec<usize> {
+        self.indices_of(name)
+            .map_or_else(Vec::new, Iterator::collect)
+    }
+}
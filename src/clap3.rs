@@ -0,0 +1,804 @@
+use crate::{file_name, AppName, ArgInfo, Clap, CliType, Matches};
+use clap3::{Arg, ArgAction, Command};
+use config::ConfigError;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+pub type ArgMatches = clap3::ArgMatches;
+pub type App = Command<'static>;
+
+impl From<Command<'static>> for Clap {
+    fn from(app: Command<'static>) -> Clap {
+        Clap::new(app)
+    }
+}
+
+impl Clap {
+    pub fn new(app: Command<'static>) -> Self {
+        Self::from_args(app, env::args_os())
+    }
+
+    pub fn from_args<I>(mut app: Command<'static>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        app.build();
+        Self::from_matches(
+            Self::get_args_types(&app, None),
+            Self::get_args_info(&app, None),
+            Some(Self::get_app_name(&app, args.first())),
+            app.get_matches_from(args),
+        )
+    }
+
+    /// Like `from_args`, but only walks the args of the chosen subcommands, for apps
+    /// with deep subcommand trees. The others are known without their args, e.g. to
+    /// `known_keys` and `arg_types_recursive`.
+    pub fn from_args_lazy<I>(app: Command<'static>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Self::try_from_args_lazy(app, args).unwrap_or_else(|error| error.exit())
+    }
+
+    /// Like `from_args_lazy`, but returns parse errors instead of exiting the process.
+    pub fn try_from_args_lazy<I>(mut app: Command<'static>, args: I) -> Result<Self, clap3::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        app.build();
+        let app_name = Self::get_app_name(&app, args.first());
+        let matches = app.try_get_matches_from_mut(args)?;
+        Ok(Self::from_matches(
+            Self::get_args_types(&app, Some(&matches)),
+            Self::get_args_info(&app, Some(&matches)),
+            Some(app_name),
+            matches,
+        ))
+    }
+
+    /// Like `from_args`, but keeps the app for `Clap::app` and renders its help
+    /// for `Clap::help`.
+    pub fn with_app<I>(app: Command<'static>, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let mut help = Vec::new();
+        let help = app.clone().write_help(&mut help).map(|_| help);
+        Self {
+            help: help
+                .ok()
+                .map(|help| String::from_utf8_lossy(&help).into_owned()),
+            app: Some(app.clone()),
+            ..Self::from_args(app, args)
+        }
+    }
+
+    /// The app kept by `with_app`, e.g. to print its help again when the config
+    /// fails to validate.
+    pub fn app(&self) -> Option<&Command<'static>> {
+        self.app.as_ref()
+    }
+
+    /// Like `from_args`, but returns parse errors instead of exiting the process.
+    pub fn try_from_args<I>(mut app: Command<'static>, args: I) -> Result<Self, clap3::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        app.build();
+        Ok(Self::from_matches(
+            Self::get_args_types(&app, None),
+            Self::get_args_info(&app, None),
+            Some(Self::get_app_name(&app, args.first())),
+            app.try_get_matches_from(args)?,
+        ))
+    }
+
+    /// Whether `error` from `try_from_args` only asks to show the help, after `--help`
+    /// or when `ArgRequiredElseHelp` got no args, rather than being a parse error.
+    pub fn is_help_request(error: &clap3::Error) -> bool {
+        matches!(
+            error.kind(),
+            clap3::ErrorKind::DisplayHelp
+                | clap3::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        )
+    }
+
+    /// Like `try_from_args`, but applies `configure` to the source first, so missing
+    /// required args are reported by their config keys, e.g. after `rename`.
+    /// Other parse errors are wrapped as they are.
+    pub fn try_from_args_with<I, F>(
+        app: Command<'static>,
+        args: I,
+        configure: F,
+    ) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+        F: FnOnce(Self) -> Self,
+    {
+        fn relax(mut app: Command<'static>) -> Command<'static> {
+            let ids: Vec<&'static str> = app
+                .get_arguments()
+                .filter(|arg| arg.is_required_set())
+                .map(Arg::get_id)
+                .collect();
+            for id in ids {
+                app = app.mut_arg(id, |arg| arg.required(false));
+            }
+            let names: Vec<String> = app
+                .get_subcommands()
+                .map(|subcommand| subcommand.get_name().to_owned())
+                .collect();
+            for name in names {
+                app = app.mut_subcommand(&*name, relax);
+            }
+            app
+        }
+
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let error = match Self::try_from_args(app.clone(), args.clone()) {
+            Ok(clap) => return Ok(configure(clap)),
+            Err(error) if error.kind() == clap3::ErrorKind::MissingRequiredArgument => error,
+            Err(error) => return Err(ConfigError::Foreign(Box::new(error))),
+        };
+        match Self::try_from_args(relax(app.clone()), args) {
+            Ok(mut clap) => {
+                // Which args are required is only known from the original app.
+                let mut app = app;
+                app.build();
+                clap.info = Self::get_args_info(&app, None);
+                Err(configure(clap).missing_required_error(error))
+            }
+            Err(_) => Err(ConfigError::Foreign(Box::new(error))),
+        }
+    }
+
+    /// Where the value collected under `key` came from: the command line, an `env`
+    /// variable or a default. `None` for absent args and args of subcommands that
+    /// weren't chosen.
+    pub fn value_source(&self, key: &str) -> Option<clap3::ValueSource> {
+        let (name, matches) = self.arg_for_key(key)?;
+        matches.value_source(name)
+    }
+
+    fn get_app_name(app: &Command<'static>, arg0: Option<&OsString>) -> AppName {
+        // Like clap, the first arg names the binary unless it was set on the app.
+        let arg0 = arg0.filter(|_| !app.is_no_binary_name_set());
+        AppName {
+            name: app.get_name().to_owned(),
+            bin_name: app
+                .get_bin_name()
+                .map(str::to_owned)
+                .or_else(|| file_name(arg0?)),
+        }
+    }
+
+    fn get_args_types(
+        app: &Command<'static>,
+        chosen: Option<&ArgMatches>,
+    ) -> HashMap<String, CliType> {
+        fn convert(arg: &Arg<'static>) -> CliType {
+            let multiple = arg.is_multiple_values_set() || arg.get_value_delimiter().is_some();
+
+            // Args with an action allow multiple occurrences whatever they collect,
+            // so only the legacy ones are typed from their settings.
+            match arg.get_action() {
+                ArgAction::Count => CliType::Count,
+                ArgAction::SetTrue | ArgAction::SetFalse => CliType::Boolean,
+                ArgAction::Append => CliType::Multiple,
+                ArgAction::Set => CliType::new(true, multiple),
+                _ => CliType::new(
+                    arg.is_takes_value_set() || arg.is_positional(),
+                    multiple || arg.is_multiple_occurrences_set(),
+                ),
+            }
+        }
+
+        app.get_subcommands()
+            .map(|subcommand| {
+                let mut args =
+                    match chosen.map(|matches| matches.subcommand_matches(subcommand.get_name())) {
+                        // Only the chosen branch is walked for `from_args_lazy`.
+                        Some(None) => HashMap::new(),
+                        submatches => Self::get_args_types(subcommand, submatches.flatten()),
+                    };
+                // Global args are copied into subcommands and their values are propagated
+                // back up, so collect them at the top level only.
+                for arg in app.get_arguments().filter(|arg| arg.is_global_set()) {
+                    args.remove(arg.get_id());
+                }
+                (subcommand.get_name().to_owned(), CliType::Subcommand(args))
+            })
+            .chain(Self::get_user_arguments(app).map(|arg| (arg.get_id().to_owned(), convert(arg))))
+            .collect()
+    }
+
+    fn get_args_info(
+        app: &Command<'static>,
+        chosen: Option<&ArgMatches>,
+    ) -> HashMap<String, ArgInfo> {
+        let negate_reqs = app.is_subcommand_negates_reqs_set();
+        // Like with clap 2, options and flags come first in the declaration order,
+        // then positionals by index and subcommands.
+        let (mut positionals, mut args): (Vec<_>, Vec<_>) =
+            Self::get_user_arguments(app).partition(|arg| arg.is_positional());
+        positionals.sort_by_key(|arg| arg.get_index());
+        args.extend(positionals);
+        let subcommands = args.len();
+        app.get_subcommands()
+            .enumerate()
+            .map(|(order, app)| {
+                (
+                    app.get_name().to_owned(),
+                    ArgInfo {
+                        order: subcommands + order,
+                        args: match chosen.map(|matches| matches.subcommand_matches(app.get_name()))
+                        {
+                            Some(None) => HashMap::new(),
+                            submatches => Self::get_args_info(app, submatches.flatten()),
+                        },
+                        ..ArgInfo::default()
+                    },
+                )
+            })
+            .chain(args.into_iter().enumerate().map(|(order, arg)| {
+                (
+                    arg.get_id().to_owned(),
+                    ArgInfo {
+                        delimiter: arg.get_value_delimiter(),
+                        default_values: arg
+                            .get_default_values()
+                            .iter()
+                            .map(|&value| value.to_owned())
+                            .collect(),
+                        has_default: !arg.get_default_values().is_empty(),
+                        env: arg.get_env().is_some(),
+                        possible_values: arg
+                            .get_possible_values()
+                            .into_iter()
+                            .flatten()
+                            .map(|value| value.get_name().to_owned())
+                            .collect(),
+                        short: arg.get_short(),
+                        long: arg.get_long().map(str::to_owned),
+                        aliases: arg
+                            .get_visible_aliases()
+                            .into_iter()
+                            .flatten()
+                            .map(str::to_owned)
+                            .collect(),
+                        hidden: arg.is_hide_set(),
+                        order,
+                        help: arg.get_help().map(str::to_owned),
+                        required: arg.is_required_set(),
+                        subcommands_negate_reqs: negate_reqs,
+                        index: arg.get_index(),
+                        path_hint: matches!(
+                            arg.get_value_hint(),
+                            clap3::ValueHint::AnyPath
+                                | clap3::ValueHint::FilePath
+                                | clap3::ValueHint::DirPath
+                                | clap3::ValueHint::ExecutablePath
+                        ),
+                        ..ArgInfo::default()
+                    },
+                )
+            }))
+            .collect()
+    }
+
+    fn get_user_arguments<'a>(app: &'a Command<'static>) -> impl Iterator<Item = &'a Arg<'static>> {
+        app.get_arguments()
+            .filter(|arg| !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version))
+    }
+}
+
+impl Matches for ArgMatches {
+    fn raw_values(&self, name: &str) -> Option<Vec<&OsStr>> {
+        self.try_get_raw(name).ok().flatten().map(Iterator::collect)
+    }
+
+    fn occurrences(&self, name: &str) -> u64 {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return 0;
+        }
+        // Args with an action don't bump `occurrences_of`.
+        match (self.occurrences_of(name), self.value_source(name)) {
+            (0, Some(clap3::ValueSource::CommandLine)) => self.count(name).max(1),
+            (occurrences, _) => occurrences,
+        }
+    }
+
+    fn is_default(&self, name: &str) -> Option<bool> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return None;
+        }
+        Some(self.value_source(name) == Some(clap3::ValueSource::DefaultValue))
+    }
+
+    fn is_env(&self, name: &str) -> Option<bool> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return None;
+        }
+        Some(self.value_source(name) == Some(clap3::ValueSource::EnvVariable))
+    }
+
+    fn count(&self, name: &str) -> u64 {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return 0;
+        }
+        match self.try_get_one::<u8>(name) {
+            Ok(Some(count)) => u64::from(*count),
+            _ => self.occurrences_of(name),
+        }
+    }
+
+    fn flag(&self, name: &str) -> Option<bool> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return None;
+        }
+        match self.value_source(name) {
+            None | Some(clap3::ValueSource::DefaultValue) => None,
+            // Flags without an action have no value and count as set when given.
+            Some(_) => match self.try_get_one::<bool>(name) {
+                Ok(Some(value)) => Some(*value),
+                _ => Some(true),
+            },
+        }
+    }
+
+    fn flag_default(&self, name: &str) -> bool {
+        // `SetTrue` and `SetFalse` flags default to the value they don't set.
+        self.try_contains_id(name).unwrap_or(false)
+            && self.try_get_one::<bool>(name).ok().flatten() == Some(&true)
+    }
+
+    fn indices(&self, name: &str) -> Vec<usize> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return Vec::new();
+        }
+        self.indices_of(name)
+            .map_or_else(Vec::new, Iterator::collect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Source, Value};
+    use serde_derive::Deserialize;
+
+    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+    #[serde(default)]
+    pub struct Config {
+        pub format: Option<String>,
+        pub verbosity: usize,
+        pub subcommand: Option<SubConfig>,
+        pub mode: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+    #[serde(default)]
+    pub struct SubConfig {
+        pub ids: Vec<u32>,
+        pub flag: bool,
+    }
+
+    fn new_app() -> Command<'static> {
+        Command::new("app")
+            .arg(
+                Arg::new("format")
+                    .takes_value(true)
+                    .short('f')
+                    .long("format"),
+            )
+            .arg(
+                Arg::new("verbosity")
+                    .short('v')
+                    .long("verbose")
+                    .multiple_occurrences(true),
+            )
+            .subcommand(
+                Command::new("subcommand")
+                    .arg(Arg::new("flag").short('F').long("flag"))
+                    .arg(
+                        Arg::new("ids")
+                            .short('i')
+                            .long("id")
+                            .required(true)
+                            .takes_value(true)
+                            .multiple_occurrences(true),
+                    ),
+            )
+    }
+
+    fn new_clap_config<I>(args: I) -> Clap
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Clap::from_args(new_app(), args).subcommand_field("mode")
+    }
+
+    fn test_clap_with_args(args: Vec<&str>, expected: Config) {
+        let mut conf = config::Config::new();
+        conf.merge(new_clap_config(args)).unwrap();
+        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    }
+
+    fn test_clap_over_file(file: &str, args: Vec<&str>, expected: Config) {
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(file, config::FileFormat::Toml))
+            .unwrap()
+            .merge(new_clap_config(args))
+            .unwrap();
+        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_clap() {
+        test_clap_with_args(
+            vec![
+                "myprog",
+                "-vvv",
+                "--format=json",
+                "subcommand",
+                "-i1",
+                "-i2",
+                "-i3",
+            ],
+            Config {
+                format: Some("json".into()),
+                verbosity: 3,
+                subcommand: Some(SubConfig {
+                    ids: vec![1, 2, 3],
+                    flag: false,
+                }),
+                mode: Some("subcommand".into()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_absent_boolean_keeps_file_value() {
+        test_clap_over_file(
+            "[subcommand]\nflag = true\n",
+            vec!["myprog", "subcommand", "-i1"],
+            Config {
+                subcommand: Some(SubConfig {
+                    ids: vec![1],
+                    flag: true,
+                }),
+                mode: Some("subcommand".into()),
+                ..Config::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_zero_count_keeps_file_value() {
+        test_clap_over_file(
+            "verbosity = 5\n",
+            vec!["myprog"],
+            Config {
+                verbosity: 5,
+                ..Config::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_flag_actions() {
+        let new_app = || {
+            Command::new("app")
+                .arg(Arg::new("debug").long("debug").action(ArgAction::SetTrue))
+                .arg(Arg::new("verbosity").short('v').action(ArgAction::Count))
+                .arg(Arg::new("format").long("format").action(ArgAction::Set))
+        };
+
+        let clap = Clap::from_args(new_app(), vec!["myprog", "--debug", "-vv", "--format=json"]);
+        let values = clap.collect().unwrap();
+        assert_eq!(values["debug"], Value::new(None, true));
+        assert_eq!(values["verbosity"], Value::new(None, 2));
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(clap.to_argv(), vec!["--debug", "--format=json", "-vv"]);
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn test_from_parts_with_unknown_args() {
+        let matches = Command::new("app")
+            .arg(Arg::new("format").long("format").takes_value(true))
+            .get_matches_from(vec!["myprog", "--format", "json"]);
+        let mut args = HashMap::new();
+        args.insert("format".to_owned(), CliType::Single);
+        args.insert("debug".to_owned(), CliType::Boolean);
+        args.insert("verbosity".to_owned(), CliType::Count);
+
+        let clap = Clap::from_parts(args, matches);
+        let values = clap.collect().unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(clap.to_argv(), vec!["--format", "json"]);
+    }
+
+    #[test]
+    fn test_diff_from_defaults() {
+        let app = Command::new("app")
+            .arg(
+                Arg::new("port")
+                    .long("port")
+                    .takes_value(true)
+                    .default_value("80"),
+            )
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .takes_value(true)
+                    .default_value("yaml"),
+            );
+        let clap = Clap::from_args(app, vec!["myprog", "--port", "80", "--format", "json"]);
+
+        let values = clap.diff_from_defaults();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["format"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_with_app() {
+        let clap = Clap::with_app(new_app(), vec!["myprog", "--format", "json"]);
+
+        let mut help = Vec::new();
+        clap.app().unwrap().clone().write_help(&mut help).unwrap();
+        let help = String::from_utf8(help).unwrap();
+        assert!(help.contains("--format <format>"));
+        assert_eq!(clap.help(), Some(help.as_str()));
+        assert!(Clap::from_args(new_app(), vec!["myprog"]).app().is_none());
+    }
+
+    #[test]
+    fn test_from_args_lazy() {
+        let new_app =
+            || new_app().subcommand(Command::new("other").arg(Arg::new("name").long("name")));
+        let args = vec!["myprog", "-vv", "subcommand", "-i1", "-i2", "-F"];
+        let lazy = Clap::from_args_lazy(new_app(), args.clone()).subcommand_field("mode");
+        let eager = Clap::from_args(new_app(), args).subcommand_field("mode");
+
+        assert_eq!(lazy.collect().unwrap(), eager.collect().unwrap());
+        assert_eq!(lazy.args["subcommand"], eager.args["subcommand"]);
+        // `other` wasn't chosen, so its args were never walked.
+        assert_eq!(lazy.args["other"], CliType::Subcommand(HashMap::new()));
+        assert!(lazy.info["other"].args.is_empty());
+        assert!(!eager.info["other"].args.is_empty());
+
+        let error = Clap::try_from_args_lazy(new_app(), vec!["myprog", "other", "--bogus"])
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), clap3::ErrorKind::UnknownArgument);
+    }
+
+    #[test]
+    fn test_coerce_numbers() {
+        let app = Command::new("app")
+            .arg(Arg::new("port").long("port").takes_value(true))
+            .arg(Arg::new("ratio").long("ratio").takes_value(true))
+            .arg(Arg::new("name").long("name").takes_value(true));
+        let clap = Clap::from_args(
+            app,
+            vec![
+                "myprog", "--port", "8080", "--ratio", "1.5", "--name", "foo",
+            ],
+        )
+        .coerce_numbers(true);
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["port"], Value::new(None, 8080));
+        assert_eq!(values["ratio"], Value::new(None, 1.5));
+        assert_eq!(values["name"], Value::new(None, "foo"));
+    }
+
+    #[test]
+    fn test_value_delimiter() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Ids {
+            ids: Vec<u32>,
+        }
+
+        let app = Command::new("app").arg(Arg::new("ids").long("ids").value_delimiter(','));
+        let mut conf = config::Config::new();
+        conf.merge(Clap::from_args(app, vec!["myprog", "--ids=1,2,3"]))
+            .unwrap();
+        assert_eq!(conf.try_into::<Ids>().unwrap(), Ids { ids: vec![1, 2, 3] });
+    }
+
+    #[test]
+    fn test_subcommand_alias() {
+        let app = Command::new("app").subcommand(
+            Command::new("subcommand")
+                .alias("sub")
+                .arg(Arg::new("flag").long("flag")),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "sub", "--flag"])
+            .subcommand_field("mode")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["mode"], Value::new(None, "subcommand"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert_eq!(subcommand["flag"], Value::new(None, true));
+    }
+
+    #[test]
+    fn test_global_args() {
+        let app = Command::new("app")
+            .arg(Arg::new("verbose").long("verbose").global(true))
+            .arg(
+                Arg::new("level")
+                    .long("level")
+                    .global(true)
+                    .takes_value(true),
+            )
+            .subcommand(Command::new("subcommand"));
+        let values = Clap::from_args(
+            app,
+            vec!["myprog", "subcommand", "--verbose", "--level", "debug"],
+        )
+        .collect()
+        .unwrap();
+
+        assert_eq!(values["verbose"], Value::new(None, true));
+        assert_eq!(values["level"], Value::new(None, "debug"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert!(subcommand.is_empty());
+    }
+
+    #[test]
+    fn test_try_from_args_with_missing_keys() {
+        let app = new_app().arg(
+            Arg::new("output")
+                .long("output")
+                .required(true)
+                .takes_value(true),
+        );
+        let error = Clap::try_from_args_with(app, vec!["myprog", "subcommand"], |clap| {
+            clap.rename("output", "output_file").key_prefix("app")
+        })
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "missing required config keys: app.output_file, app.subcommand.ids"
+        );
+    }
+
+    #[test]
+    fn test_require_value_delimiter() {
+        let app = Command::new("app")
+            .arg(
+                Arg::new("tags")
+                    .long("tag")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .use_value_delimiter(true)
+                    .require_value_delimiter(true),
+            )
+            .arg(Arg::new("input"));
+        let values = Clap::from_args(app, vec!["myprog", "--tag", "a,b", "input", "--tag", "c"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["tags"], Value::new(None, vec!["a", "b", "c"]));
+        assert_eq!(values["input"], Value::new(None, "input"));
+    }
+
+    #[test]
+    fn test_value_source() {
+        let app = Command::new("app")
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .takes_value(true)
+                    .default_value("yaml"),
+            )
+            .arg(Arg::new("level").long("level").takes_value(true))
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .takes_value(true)
+                    .env("CLAP_CONFIG_TEST_UNSET_COLOR")
+                    .default_value("auto"),
+            );
+        let clap = Clap::from_args(app, vec!["myprog", "--level", "debug"]).ignore_env_values(true);
+
+        assert_eq!(
+            clap.value_source("format"),
+            Some(clap3::ValueSource::DefaultValue)
+        );
+        assert_eq!(
+            clap.value_source("level"),
+            Some(clap3::ValueSource::CommandLine)
+        );
+        assert_eq!(clap.value_source("missing"), None);
+        // The env variable is unset, so `ignore_env_values` keeps the default.
+        assert_eq!(
+            clap.value_source("color"),
+            Some(clap3::ValueSource::DefaultValue)
+        );
+        assert_eq!(clap.collect().unwrap()["color"], Value::new(None, "auto"));
+    }
+
+    #[test]
+    fn test_multiple_default_values() {
+        let new_app = || {
+            Command::new("app").arg(
+                Arg::new("tags")
+                    .long("tag")
+                    .takes_value(true)
+                    .multiple_occurrences(true)
+                    .default_values(&["a", "b"]),
+            )
+        };
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["tags"], Value::new(None, vec!["a", "b"]));
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("tags"));
+
+        let values = Clap::from_args(new_app(), vec!["myprog", "--tag", "c"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["tags"], Value::new(None, vec!["c"]));
+    }
+
+    #[test]
+    fn test_respect_value_hints() {
+        use clap3::ValueHint;
+
+        let home = std::env::var("HOME").unwrap();
+        let new_app = || {
+            Command::new("app")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .takes_value(true)
+                        .value_hint(ValueHint::FilePath),
+                )
+                .arg(Arg::new("name").long("name").takes_value(true))
+        };
+        let args = vec!["myprog", "--config", "~/app.toml", "--name", "~/x"];
+
+        let values = Clap::from_args(new_app(), args.clone())
+            .respect_value_hints(true)
+            .collect()
+            .unwrap();
+        assert_eq!(
+            values["config"],
+            Value::new(None, format!("{}/app.toml", home))
+        );
+        assert_eq!(values["name"], Value::new(None, "~/x"));
+
+        let values = Clap::from_args(new_app(), args).collect().unwrap();
+        assert_eq!(values["config"], Value::new(None, "~/app.toml"));
+    }
+}
@@ -0,0 +1,902 @@
+use crate::{file_name, AppName, ArgInfo, Clap, CliType, Matches};
+use clap4::parser::ValueSource;
+use clap4::{Arg, ArgAction, Command};
+use config::ConfigError;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+pub type ArgMatches = clap4::ArgMatches;
+pub type App = Command;
+
+impl From<Command> for Clap {
+    fn from(app: Command) -> Clap {
+        Clap::new(app)
+    }
+}
+
+impl Clap {
+    pub fn new(app: Command) -> Self {
+        Self::from_args(app, env::args_os())
+    }
+
+    pub fn from_args<I>(mut app: Command, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        app.build();
+        Self::from_matches(
+            Self::get_args_types(&app, None),
+            Self::get_args_info(&app, None),
+            Some(Self::get_app_name(&app, args.first())),
+            app.get_matches_from(args),
+        )
+    }
+
+    /// Like `from_args`, but only walks the args of the chosen subcommands, for apps
+    /// with deep subcommand trees. The others are known without their args, e.g. to
+    /// `known_keys` and `arg_types_recursive`.
+    pub fn from_args_lazy<I>(app: Command, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Self::try_from_args_lazy(app, args).unwrap_or_else(|error| error.exit())
+    }
+
+    /// Like `from_args_lazy`, but returns parse errors instead of exiting the process.
+    pub fn try_from_args_lazy<I>(mut app: Command, args: I) -> Result<Self, clap4::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        app.build();
+        let app_name = Self::get_app_name(&app, args.first());
+        let matches = app.try_get_matches_from_mut(args)?;
+        Ok(Self::from_matches(
+            Self::get_args_types(&app, Some(&matches)),
+            Self::get_args_info(&app, Some(&matches)),
+            Some(app_name),
+            matches,
+        ))
+    }
+
+    /// Like `from_args`, but keeps the app for `Clap::app` and renders its help
+    /// for `Clap::help`.
+    pub fn with_app<I>(app: Command, args: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Self {
+            help: Some(app.clone().render_help().to_string()),
+            app: Some(app.clone()),
+            ..Self::from_args(app, args)
+        }
+    }
+
+    /// The app kept by `with_app`, e.g. to print its help again when the config
+    /// fails to validate.
+    pub fn app(&self) -> Option<&Command> {
+        self.app.as_ref()
+    }
+
+    /// Like `from_args`, but returns parse errors instead of exiting the process.
+    pub fn try_from_args<I>(mut app: Command, args: I) -> Result<Self, clap4::Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        app.build();
+        Ok(Self::from_matches(
+            Self::get_args_types(&app, None),
+            Self::get_args_info(&app, None),
+            Some(Self::get_app_name(&app, args.first())),
+            app.try_get_matches_from(args)?,
+        ))
+    }
+
+    /// Use matches that were already parsed from `app`, e.g. with the derive API:
+    ///
+    /// ```
+    /// # extern crate clap4 as clap;
+    /// use clap::{CommandFactory, Parser};
+    /// use clap_config::Clap;
+    ///
+    /// #[derive(Parser)]
+    /// struct Args {
+    ///     #[arg(long)]
+    ///     format: Option<String>,
+    /// }
+    ///
+    /// let matches = Args::command().get_matches_from(["myprog", "--format", "json"]);
+    /// let clap = Clap::from_arg_matches(&Args::command(), matches);
+    /// ```
+    pub fn from_arg_matches(app: &Command, matches: ArgMatches) -> Self {
+        let mut app = app.clone();
+        app.build();
+        Self::from_matches(
+            Self::get_args_types(&app, None),
+            Self::get_args_info(&app, None),
+            Some(Self::get_app_name(&app, None)),
+            matches,
+        )
+    }
+
+    /// Whether `error` from `try_from_args` only asks to show the help, after `--help`
+    /// or when `ArgRequiredElseHelp` got no args, rather than being a parse error.
+    pub fn is_help_request(error: &clap4::Error) -> bool {
+        matches!(
+            error.kind(),
+            clap4::error::ErrorKind::DisplayHelp
+                | clap4::error::ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        )
+    }
+
+    /// Like `try_from_args`, but applies `configure` to the source first, so missing
+    /// required args are reported by their config keys, e.g. after `rename`.
+    /// Other parse errors are wrapped as they are.
+    pub fn try_from_args_with<I, F>(
+        app: Command,
+        args: I,
+        configure: F,
+    ) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+        F: FnOnce(Self) -> Self,
+    {
+        fn relax(app: Command) -> Command {
+            app.mut_args(|arg| arg.required(false))
+                .mut_subcommands(relax)
+        }
+
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let error = match Self::try_from_args(app.clone(), args.clone()) {
+            Ok(clap) => return Ok(configure(clap)),
+            Err(error) if error.kind() == clap4::error::ErrorKind::MissingRequiredArgument => error,
+            Err(error) => return Err(ConfigError::Foreign(Box::new(error))),
+        };
+        match Self::try_from_args(relax(app.clone()), args) {
+            Ok(mut clap) => {
+                // Which args are required is only known from the original app.
+                let mut app = app;
+                app.build();
+                clap.info = Self::get_args_info(&app, None);
+                Err(configure(clap).missing_required_error(error))
+            }
+            Err(_) => Err(ConfigError::Foreign(Box::new(error))),
+        }
+    }
+
+    /// Where the value collected under `key` came from: the command line, an `env`
+    /// variable or a default. `None` for absent args and args of subcommands that
+    /// weren't chosen.
+    pub fn value_source(&self, key: &str) -> Option<ValueSource> {
+        let (name, matches) = self.arg_for_key(key)?;
+        matches.value_source(name)
+    }
+
+    fn get_app_name(app: &Command, arg0: Option<&OsString>) -> AppName {
+        // Like clap, the first arg names the binary unless it was set on the app.
+        let arg0 = arg0.filter(|_| !app.is_no_binary_name_set());
+        AppName {
+            name: app.get_name().to_owned(),
+            bin_name: app
+                .get_bin_name()
+                .map(str::to_owned)
+                .or_else(|| file_name(arg0?)),
+        }
+    }
+
+    fn get_args_types(app: &Command, chosen: Option<&ArgMatches>) -> HashMap<String, CliType> {
+        fn convert(arg: &Arg) -> CliType {
+            let multiple = arg
+                .get_num_args()
+                .is_some_and(|range| range.max_values() > 1)
+                || arg.get_value_delimiter().is_some();
+
+            match arg.get_action() {
+                ArgAction::Count => CliType::Count,
+                ArgAction::Append => CliType::Multiple,
+                action => CliType::new(action.takes_values(), multiple),
+            }
+        }
+
+        app.get_subcommands()
+            .map(|subcommand| {
+                let mut args =
+                    match chosen.map(|matches| matches.subcommand_matches(subcommand.get_name())) {
+                        // Only the chosen branch is walked for `from_args_lazy`.
+                        Some(None) => HashMap::new(),
+                        submatches => Self::get_args_types(subcommand, submatches.flatten()),
+                    };
+                // Global args are copied into subcommands and their values are propagated
+                // back up, so collect them at the top level only.
+                for arg in app.get_arguments().filter(|arg| arg.is_global_set()) {
+                    args.remove(arg.get_id().as_str());
+                }
+                (subcommand.get_name().to_owned(), CliType::Subcommand(args))
+            })
+            .chain(
+                Self::get_user_arguments(app).map(|arg| (arg.get_id().to_string(), convert(arg))),
+            )
+            .chain(
+                app.get_groups()
+                    .filter(|&group| !group.clone().is_multiple())
+                    .map(|group| {
+                        (
+                            group.get_id().to_string(),
+                            CliType::Group(group.get_args().map(ToString::to_string).collect()),
+                        )
+                    }),
+            )
+            .collect()
+    }
+
+    fn get_args_info(app: &Command, chosen: Option<&ArgMatches>) -> HashMap<String, ArgInfo> {
+        let negate_reqs = app.is_subcommand_negates_reqs_set();
+        // Like with clap 2, options and flags come first in the declaration order,
+        // then positionals by index and subcommands.
+        let (mut positionals, mut args): (Vec<_>, Vec<_>) =
+            Self::get_user_arguments(app).partition(|arg| arg.is_positional());
+        positionals.sort_by_key(|arg| arg.get_index());
+        args.extend(positionals);
+        let subcommands = args.len();
+        app.get_subcommands()
+            .enumerate()
+            .map(|(order, app)| {
+                (
+                    app.get_name().to_owned(),
+                    ArgInfo {
+                        order: subcommands + order,
+                        args: match chosen.map(|matches| matches.subcommand_matches(app.get_name()))
+                        {
+                            Some(None) => HashMap::new(),
+                            submatches => Self::get_args_info(app, submatches.flatten()),
+                        },
+                        ..ArgInfo::default()
+                    },
+                )
+            })
+            .chain(args.into_iter().enumerate().map(|(order, arg)| {
+                (
+                    arg.get_id().to_string(),
+                    ArgInfo {
+                        delimiter: arg.get_value_delimiter(),
+                        default_values: arg
+                            .get_default_values()
+                            .iter()
+                            .map(|value| value.as_os_str().to_owned())
+                            .collect(),
+                        has_default: !arg.get_default_values().is_empty(),
+                        env: arg.get_env().is_some(),
+                        possible_values: arg
+                            .get_possible_values()
+                            .iter()
+                            .map(|value| value.get_name().to_owned())
+                            .collect(),
+                        short: arg.get_short(),
+                        long: arg.get_long().map(str::to_owned),
+                        aliases: arg
+                            .get_visible_aliases()
+                            .into_iter()
+                            .flatten()
+                            .map(str::to_owned)
+                            .collect(),
+                        hidden: arg.is_hide_set(),
+                        order,
+                        help: arg.get_help().map(ToString::to_string),
+                        required: arg.is_required_set(),
+                        subcommands_negate_reqs: negate_reqs,
+                        index: arg.get_index(),
+                        path_hint: matches!(
+                            arg.get_value_hint(),
+                            clap4::ValueHint::AnyPath
+                                | clap4::ValueHint::FilePath
+                                | clap4::ValueHint::DirPath
+                                | clap4::ValueHint::ExecutablePath
+                        ),
+                        ..ArgInfo::default()
+                    },
+                )
+            }))
+            .collect()
+    }
+
+    fn get_user_arguments(app: &Command) -> impl Iterator<Item = &Arg> {
+        app.get_arguments().filter(|arg| {
+            !matches!(
+                arg.get_action(),
+                ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+            )
+        })
+    }
+}
+
+impl Matches for ArgMatches {
+    fn raw_values(&self, name: &str) -> Option<Vec<&OsStr>> {
+        self.try_get_raw(name).ok().flatten().map(Iterator::collect)
+    }
+
+    fn occurrences(&self, name: &str) -> u64 {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return 0;
+        }
+        match self.value_source(name) {
+            Some(ValueSource::CommandLine) => self
+                .get_raw_occurrences(name)
+                .map_or(0, |occurrences| occurrences.count() as u64),
+            _ => 0,
+        }
+    }
+
+    fn is_default(&self, name: &str) -> Option<bool> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return None;
+        }
+        Some(self.value_source(name) == Some(ValueSource::DefaultValue))
+    }
+
+    fn is_env(&self, name: &str) -> Option<bool> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return None;
+        }
+        Some(self.value_source(name) == Some(ValueSource::EnvVariable))
+    }
+
+    fn count(&self, name: &str) -> u64 {
+        self.try_get_one::<u8>(name)
+            .ok()
+            .flatten()
+            .map_or(0, |count| u64::from(*count))
+    }
+
+    fn flag(&self, name: &str) -> Option<bool> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return None;
+        }
+        match self.value_source(name) {
+            None | Some(ValueSource::DefaultValue) => None,
+            // Args that aren't `bool` typed (e.g. with `force_type`) count as set when given.
+            Some(_) => match self.try_get_one::<bool>(name) {
+                Ok(value) => value.copied(),
+                Err(_) => Some(true),
+            },
+        }
+    }
+
+    fn flag_default(&self, name: &str) -> bool {
+        // `SetTrue` and `SetFalse` flags default to the value they don't set.
+        self.try_contains_id(name).unwrap_or(false)
+            && self.try_get_one::<bool>(name).ok().flatten() == Some(&true)
+    }
+
+    fn indices(&self, name: &str) -> Vec<usize> {
+        if !self.try_contains_id(name).unwrap_or(false) {
+            return Vec::new();
+        }
+        self.indices_of(name)
+            .map_or_else(Vec::new, Iterator::collect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::{Source, Value};
+    use serde_derive::Deserialize;
+
+    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+    #[serde(default)]
+    pub struct Config {
+        pub format: Option<String>,
+        pub verbosity: usize,
+        pub subcommand: Option<SubConfig>,
+        pub mode: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+    #[serde(default)]
+    pub struct SubConfig {
+        pub ids: Vec<u32>,
+        pub flag: bool,
+    }
+
+    fn new_app() -> Command {
+        Command::new("app")
+            .arg(Arg::new("format").short('f').long("format"))
+            .arg(
+                Arg::new("verbosity")
+                    .short('v')
+                    .long("verbose")
+                    .action(ArgAction::Count),
+            )
+            .subcommand(
+                Command::new("subcommand")
+                    .arg(
+                        Arg::new("flag")
+                            .short('F')
+                            .long("flag")
+                            .action(ArgAction::SetTrue),
+                    )
+                    .arg(
+                        Arg::new("ids")
+                            .short('i')
+                            .long("id")
+                            .required(true)
+                            .action(ArgAction::Append),
+                    ),
+            )
+    }
+
+    fn new_clap_config<I>(args: I) -> Clap
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Clap::from_args(new_app(), args).subcommand_field("mode")
+    }
+
+    fn test_clap_with_args(args: Vec<&str>, expected: Config) {
+        let mut conf = config::Config::new();
+        conf.merge(new_clap_config(args)).unwrap();
+        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    }
+
+    fn test_clap_over_file(file: &str, args: Vec<&str>, expected: Config) {
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(file, config::FileFormat::Toml))
+            .unwrap()
+            .merge(new_clap_config(args))
+            .unwrap();
+        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_clap() {
+        test_clap_with_args(
+            vec![
+                "myprog",
+                "-vvv",
+                "--format=json",
+                "subcommand",
+                "-i1",
+                "-i2",
+                "-i3",
+            ],
+            Config {
+                format: Some("json".into()),
+                verbosity: 3,
+                subcommand: Some(SubConfig {
+                    ids: vec![1, 2, 3],
+                    flag: false,
+                }),
+                mode: Some("subcommand".into()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_absent_boolean_keeps_file_value() {
+        test_clap_over_file(
+            "[subcommand]\nflag = true\n",
+            vec!["myprog", "subcommand", "-i1"],
+            Config {
+                subcommand: Some(SubConfig {
+                    ids: vec![1],
+                    flag: true,
+                }),
+                mode: Some("subcommand".into()),
+                ..Config::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_zero_count_keeps_file_value() {
+        test_clap_over_file(
+            "verbosity = 5\n",
+            vec!["myprog"],
+            Config {
+                verbosity: 5,
+                ..Config::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_from_parts_with_unknown_args() {
+        let matches = Command::new("app")
+            .arg(Arg::new("format").long("format"))
+            .get_matches_from(vec!["myprog", "--format", "json"]);
+        let mut args = HashMap::new();
+        args.insert("format".to_owned(), CliType::Single);
+        args.insert("debug".to_owned(), CliType::Boolean);
+        args.insert("verbosity".to_owned(), CliType::Count);
+
+        let clap = Clap::from_parts(args, matches);
+        let values = clap.collect().unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(clap.to_argv(), vec!["--format", "json"]);
+    }
+
+    #[test]
+    fn test_diff_from_defaults() {
+        let app = Command::new("app")
+            .arg(Arg::new("port").long("port").default_value("80"))
+            .arg(Arg::new("format").long("format").default_value("yaml"));
+        let clap = Clap::from_args(app, vec!["myprog", "--port", "80", "--format", "json"]);
+
+        let values = clap.diff_from_defaults();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values["format"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_from_args_lazy() {
+        let new_app =
+            || new_app().subcommand(Command::new("other").arg(Arg::new("name").long("name")));
+        let args = vec!["myprog", "-vv", "subcommand", "-i1", "-i2", "-F"];
+        let lazy = Clap::from_args_lazy(new_app(), args.clone()).subcommand_field("mode");
+        let eager = Clap::from_args(new_app(), args).subcommand_field("mode");
+
+        assert_eq!(lazy.collect().unwrap(), eager.collect().unwrap());
+        assert_eq!(lazy.args["subcommand"], eager.args["subcommand"]);
+        // `other` wasn't chosen, so its args were never walked.
+        assert_eq!(lazy.args["other"], CliType::Subcommand(HashMap::new()));
+        assert!(lazy.info["other"].args.is_empty());
+        assert!(!eager.info["other"].args.is_empty());
+
+        let error = Clap::try_from_args_lazy(new_app(), vec!["myprog", "other", "--bogus"])
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), clap4::error::ErrorKind::UnknownArgument);
+    }
+
+    #[test]
+    fn test_coerce_numbers() {
+        let app = Command::new("app")
+            .arg(Arg::new("port").long("port"))
+            .arg(Arg::new("ratio").long("ratio"))
+            .arg(Arg::new("name").long("name"));
+        let clap = Clap::from_args(
+            app,
+            vec![
+                "myprog", "--port", "8080", "--ratio", "1.5", "--name", "foo",
+            ],
+        )
+        .coerce_numbers(true);
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["port"], Value::new(None, 8080));
+        assert_eq!(values["ratio"], Value::new(None, 1.5));
+        assert_eq!(values["name"], Value::new(None, "foo"));
+    }
+
+    #[test]
+    fn test_value_delimiter() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Ids {
+            ids: Vec<u32>,
+        }
+
+        let app = Command::new("app").arg(Arg::new("ids").long("ids").value_delimiter(','));
+        let mut conf = config::Config::new();
+        conf.merge(Clap::from_args(app, vec!["myprog", "--ids=1,2,3"]))
+            .unwrap();
+        assert_eq!(conf.try_into::<Ids>().unwrap(), Ids { ids: vec![1, 2, 3] });
+    }
+
+    #[test]
+    fn test_subcommand_alias() {
+        let app = Command::new("app").subcommand(
+            Command::new("subcommand")
+                .alias("sub")
+                .arg(Arg::new("flag").long("flag").action(ArgAction::SetTrue)),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "sub", "--flag"])
+            .subcommand_field("mode")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["mode"], Value::new(None, "subcommand"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert_eq!(subcommand["flag"], Value::new(None, true));
+    }
+
+    #[test]
+    fn test_global_args() {
+        let app = Command::new("app")
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .global(true)
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("level").long("level").global(true))
+            .subcommand(Command::new("subcommand"));
+        let values = Clap::from_args(
+            app,
+            vec!["myprog", "subcommand", "--verbose", "--level", "debug"],
+        )
+        .collect()
+        .unwrap();
+
+        assert_eq!(values["verbose"], Value::new(None, true));
+        assert_eq!(values["level"], Value::new(None, "debug"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert!(subcommand.is_empty());
+    }
+
+    #[test]
+    fn test_try_from_args_with_missing_keys() {
+        let app = new_app().arg(Arg::new("output").long("output").required(true));
+        let error = Clap::try_from_args_with(app, vec!["myprog", "subcommand"], |clap| {
+            clap.rename("output", "output_file").key_prefix("app")
+        })
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "missing required config keys: app.output_file, app.subcommand.ids"
+        );
+    }
+
+    #[test]
+    fn test_positionals_in_order() {
+        let app = Command::new("app")
+            .arg(Arg::new("dest").index(2))
+            .arg(Arg::new("source").index(1))
+            .arg(Arg::new("extra").index(3).num_args(1..));
+        let clap = Clap::from_args(app, vec!["myprog", "a", "b", "c", "d"]);
+
+        assert_eq!(
+            clap.positionals_in_order(),
+            vec![
+                ("source".to_owned(), Value::new(None, "a")),
+                ("dest".to_owned(), Value::new(None, "b")),
+                ("extra".to_owned(), Value::new(None, vec!["c", "d"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_argv() {
+        let args = vec!["myprog", "-vv", "-f", "json", "subcommand", "-i", "1", "-F"];
+        let clap = Clap::from_args(new_app(), args);
+        let argv = clap.to_argv();
+
+        assert_eq!(
+            argv,
+            vec!["--format=json", "-vv", "subcommand", "--flag", "--id=1"]
+        );
+        let reparsed = Clap::from_args(new_app(), std::iter::once("myprog".to_owned()).chain(argv));
+        assert_eq!(reparsed.collect().unwrap(), clap.collect().unwrap());
+    }
+
+    #[test]
+    fn test_to_argv_set_false_and_hyphen_positionals() {
+        let new_app = || {
+            Command::new("app")
+                .arg(
+                    Arg::new("color")
+                        .long("no-color")
+                        .action(ArgAction::SetFalse),
+                )
+                .arg(Arg::new("input").num_args(1..))
+        };
+        let clap = Clap::from_args(new_app(), vec!["myprog", "--no-color", "--", "-x", "y"]);
+        let argv = clap.to_argv();
+
+        assert_eq!(argv, vec!["--no-color", "--", "-x", "y"]);
+        let reparsed = Clap::from_args(new_app(), std::iter::once("myprog".to_owned()).chain(argv));
+        assert_eq!(reparsed.collect().unwrap(), clap.collect().unwrap());
+        assert_eq!(clap.collect().unwrap()["color"], Value::new(None, false));
+    }
+
+    #[test]
+    fn test_to_argv_multiple_values() {
+        let new_app = || {
+            Command::new("app")
+                .arg(Arg::new("ids").long("ids").value_delimiter(','))
+                .arg(
+                    Arg::new("point")
+                        .long("point")
+                        .num_args(2)
+                        .action(ArgAction::Append),
+                )
+        };
+        let args = vec![
+            "myprog", "--ids", "1,2,3", "--point", "1", "2", "--point", "3", "4",
+        ];
+        let clap = Clap::from_args(new_app(), args);
+        let argv = clap.to_argv();
+
+        assert_eq!(
+            argv,
+            vec!["--ids=1,2,3", "--point", "1", "2", "--point", "3", "4"]
+        );
+        let reparsed =
+            Clap::try_from_args(new_app(), std::iter::once("myprog".to_owned()).chain(argv))
+                .unwrap();
+        assert_eq!(reparsed.collect().unwrap(), clap.collect().unwrap());
+    }
+
+    #[test]
+    fn test_emit_all_booleans_set_false() {
+        let app = Command::new("app")
+            .arg(
+                Arg::new("color")
+                    .long("no-color")
+                    .action(ArgAction::SetFalse),
+            )
+            .arg(Arg::new("force").long("force").action(ArgAction::SetTrue));
+        let values = Clap::from_args(app, vec!["myprog"])
+            .emit_all_booleans(true)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["color"], Value::new(None, true));
+        assert_eq!(values["force"], Value::new(None, false));
+    }
+
+    #[test]
+    fn test_trailing_key() {
+        let app = Command::new("app")
+            .arg(Arg::new("format").long("format"))
+            .arg(Arg::new("rest").num_args(1..).trailing_var_arg(true));
+        let clap = Clap::from_args(
+            app,
+            vec!["myprog", "--format", "json", "--", "a", "-b", "c"],
+        )
+        .trailing_key("command");
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["command"], Value::new(None, vec!["a", "-b", "c"]));
+        assert_eq!(values["format"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_with_app_help() {
+        let clap = Clap::with_app(new_app(), vec!["myprog", "--format", "json"]);
+
+        let help = clap.help().unwrap();
+        assert!(help.contains("Usage: app"));
+        assert!(help.contains("--format <format>"));
+        assert_eq!(clap.collect().unwrap()["format"], Value::new(None, "json"));
+
+        let help = clap.app().unwrap().clone().render_help().to_string();
+        assert!(help.contains("--format <format>"));
+        assert!(Clap::from_args(new_app(), vec!["myprog"]).app().is_none());
+    }
+
+    #[test]
+    fn test_subcommands_negate_reqs() {
+        let app = new_app().subcommand_negates_reqs(true).arg(
+            Arg::new("input")
+                .long("input")
+                .required(true)
+                .default_value("-"),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "subcommand", "-i", "1"])
+            .collect()
+            .unwrap();
+
+        assert!(!values.contains_key("input"));
+        assert!(values.contains_key("subcommand"));
+    }
+
+    #[test]
+    fn test_negatable() {
+        let app = Command::new("app")
+            .arg(Arg::new("color").long("color").action(ArgAction::SetTrue))
+            .arg(
+                Arg::new("no-color")
+                    .long("no-color")
+                    .action(ArgAction::SetTrue),
+            );
+        let values = Clap::from_args(app, vec!["myprog", "--color", "--no-color"])
+            .negatable("color", "color", "no-color")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["color"], Value::new(None, false));
+        assert!(!values.contains_key("no-color"));
+    }
+
+    #[test]
+    fn test_value_source() {
+        let app = Command::new("app")
+            .arg(Arg::new("format").long("format").default_value("yaml"))
+            .arg(Arg::new("level").long("level"))
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .env("CLAP_CONFIG_TEST_UNSET_COLOR")
+                    .default_value("auto"),
+            );
+        let clap = Clap::from_args(app, vec!["myprog", "--level", "debug"]).ignore_env_values(true);
+
+        assert_eq!(clap.value_source("format"), Some(ValueSource::DefaultValue));
+        assert_eq!(clap.value_source("level"), Some(ValueSource::CommandLine));
+        assert_eq!(clap.value_source("missing"), None);
+        // The env variable is unset, so `ignore_env_values` keeps the default.
+        assert_eq!(clap.value_source("color"), Some(ValueSource::DefaultValue));
+        assert_eq!(clap.collect().unwrap()["color"], Value::new(None, "auto"));
+    }
+
+    #[test]
+    fn test_multiple_default_values() {
+        let new_app = || {
+            Command::new("app").arg(
+                Arg::new("tags")
+                    .long("tag")
+                    .action(ArgAction::Append)
+                    .default_values(["a", "b"]),
+            )
+        };
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["tags"], Value::new(None, vec!["a", "b"]));
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("tags"));
+
+        let values = Clap::from_args(new_app(), vec!["myprog", "--tag", "c"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["tags"], Value::new(None, vec!["c"]));
+    }
+
+    #[test]
+    fn test_is_help_request() {
+        let new_app = || new_app().arg_required_else_help(true);
+        let error = Clap::try_from_args(new_app(), vec!["myprog"]).unwrap_err();
+        assert!(Clap::is_help_request(&error));
+
+        let error = Clap::try_from_args(new_app(), vec!["myprog", "--bogus"]).unwrap_err();
+        assert!(!Clap::is_help_request(&error));
+    }
+
+    #[test]
+    fn test_respect_value_hints() {
+        use clap4::ValueHint;
+
+        let home = std::env::var("HOME").unwrap();
+        let new_app = || {
+            Command::new("app")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .value_hint(ValueHint::FilePath),
+                )
+                .arg(Arg::new("name").long("name"))
+        };
+        let args = vec!["myprog", "--config", "~/app.toml", "--name", "~/x"];
+
+        let values = Clap::from_args(new_app(), args.clone())
+            .respect_value_hints(true)
+            .collect()
+            .unwrap();
+        assert_eq!(
+            values["config"],
+            Value::new(None, format!("{}/app.toml", home))
+        );
+        assert_eq!(values["name"], Value::new(None, "~/x"));
+
+        let values = Clap::from_args(new_app(), args).collect().unwrap();
+        assert_eq!(values["config"], Value::new(None, "~/app.toml"));
+    }
+}
@@ -0,0 +1,23 @@
+use crate::Clap;
+use figment::value::{Dict, Map};
+use figment::{Error, Metadata, Profile, Provider};
+
+/// Figment doesn't parse strings into numbers on extraction,
+/// so enable `coerce_numbers` for numeric args (or use `extract_lossy`).
+impl Provider for Clap {
+    fn metadata(&self) -> Metadata {
+        Metadata::named("command line arguments")
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, Error> {
+        // Go through `config` so dotted keys end up nested the same way as in a `Config`.
+        let mut config = config::Config::new();
+        config
+            .merge(self.clone())
+            .map_err(|error| Error::from(error.to_string()))?;
+        let dict = config
+            .try_into::<Dict>()
+            .map_err(|error| Error::from(error.to_string()))?;
+        Ok(Profile::Default.collect(dict))
+    }
+}
@@ -1,246 +1,4522 @@
-use clap::{App, ArgMatches, ArgSettings};
 use config::{ConfigError, Source, Value};
-use std::collections::HashMap;
-use std::ffi::OsString;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[cfg(not(any(feature = "clap2", feature = "clap3", feature = "clap4")))]
+compile_error!("one of the `clap2`, `clap3` or `clap4` features must be enabled");
+#[cfg(any(
+    all(feature = "clap2", feature = "clap3"),
+    all(feature = "clap2", feature = "clap4"),
+    all(feature = "clap3", feature = "clap4"),
+))]
+compile_error!("the `clap2`, `clap3` and `clap4` features are mutually exclusive");
+
+#[cfg(feature = "clap2")]
+mod clap2;
+#[cfg(feature = "clap2")]
+use clap2::ArgMatches;
+
+#[cfg(feature = "clap3")]
+mod clap3;
+#[cfg(feature = "clap3")]
+use clap3::{App, ArgMatches};
+
+#[cfg(feature = "clap4")]
+mod clap4;
+#[cfg(feature = "clap4")]
+use clap4::{App, ArgMatches};
+
+#[cfg(feature = "figment")]
+mod figment;
+#[cfg(feature = "indexmap")]
+mod ordered;
+#[cfg(any(feature = "json", feature = "toml"))]
+mod render;
 
 #[derive(Debug, Clone)]
 pub struct Clap {
     args: HashMap<String, CliType>,
-    pub matches: ArgMatches<'static>,
+    pub matches: ArgMatches,
     subcommand_field: Option<String>,
+    bin_name_field: Option<String>,
+    subcommand_fields: Vec<String>,
+    subcommand_field_for: HashMap<String, String>,
+    append_multiples: HashSet<String>,
+    append_base: HashMap<String, Vec<Value>>,
+    origin: Option<String>,
+    coerce_numbers: bool,
+    key_case: KeyCase,
+    key_map: Option<Hook<KeyMap>>,
+    renames: HashMap<String, String>,
+    case_insensitive_lookup: bool,
+    nested_keys: bool,
+    ignore_defaults: bool,
+    key_prefix: Option<String>,
+    exclude: HashSet<String>,
+    include_only: Option<HashSet<String>>,
+    os_values: bool,
+    count_as_bool: HashSet<String>,
+    count_levels: HashMap<String, Vec<String>>,
+    flatten_subcommands: bool,
+    key_from_long: bool,
+    emit_aliases: bool,
+    alias_keys: HashMap<String, Vec<String>>,
+    path_separator: String,
+    coerce_bools: bool,
+    ignore_env_values: bool,
+    dedup_multiple: HashSet<String>,
+    sort_multiple: HashSet<String>,
+    bool_strings: HashMap<String, (String, Option<String>)>,
+    negatables: Vec<(String, String, String)>,
+    emit_null: HashSet<String>,
+    emit_all_booleans: bool,
+    map_args: HashMap<String, char>,
+    tuples: HashMap<String, usize>,
+    sentinels: HashMap<String, String>,
+    paths: HashMap<String, PathOpts>,
+    respect_value_hints: bool,
+    canonicalize_choices: bool,
+    layer: Layer,
+    occurrence_counts: Option<String>,
+    default_subcommand: Option<String>,
+    post_process: Option<Hook<PostProcess>>,
+    cache: Memo,
+    trim_values: bool,
+    drop_empty: bool,
+    app_name: Option<AppName>,
+    value_maps: HashMap<String, Hook<ValueMap>>,
+    skip_hidden: bool,
+    strict_keys: bool,
+    combined: Vec<Clap>,
+    help: Option<String>,
+    #[cfg(any(feature = "clap3", feature = "clap4"))]
+    app: Option<App>,
+    info: HashMap<String, ArgInfo>,
 }
 
-#[derive(Debug, Clone)]
-enum CliType {
+/// Case conversion applied to the collected config keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyCase {
+    /// Keep arg names as they are.
+    #[default]
+    None,
+    /// Replace dashes with underscores: `log-level` becomes `log_level`.
+    SnakeCase,
+    /// Replace underscores with dashes: `log_level` becomes `log-level`.
+    KebabCase,
+    /// Lowercase the whole key: `LogLevel` becomes `loglevel`.
+    Lowercase,
+    /// Uppercase the whole key: `LogLevel` becomes `LOGLEVEL`.
+    Uppercase,
+}
+
+impl KeyCase {
+    fn apply(self, key: &str) -> String {
+        match self {
+            KeyCase::None => key.to_owned(),
+            KeyCase::SnakeCase => key.replace('-', "_"),
+            KeyCase::KebabCase => key.replace('_', "-"),
+            KeyCase::Lowercase => key.to_lowercase(),
+            KeyCase::Uppercase => key.to_uppercase(),
+        }
+    }
+}
+
+/// Normalization of path values, see `Clap::as_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PathOpts {
+    /// Replace a leading `~` with the home directory from `HOME`.
+    pub expand_tilde: bool,
+    /// Make the path absolute and resolve symlinks. Paths that don't exist
+    /// are kept as they are after the tilde expansion.
+    pub canonicalize: bool,
+}
+
+impl PathOpts {
+    fn apply(self, value: &str) -> String {
+        let mut path = PathBuf::from(value);
+        if let (true, Some(rest)) = (self.expand_tilde, value.strip_prefix('~')) {
+            if let (true, Some(home)) = (
+                rest.is_empty() || rest.starts_with('/'),
+                env::var_os("HOME"),
+            ) {
+                path = match rest.trim_start_matches('/') {
+                    "" => PathBuf::from(home),
+                    rest => Path::new(&home).join(rest),
+                };
+            }
+        }
+        if self.canonicalize {
+            if let Ok(canonical) = path.canonicalize() {
+                path = canonical;
+            }
+        }
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// How the value of an arg is collected.
+///
+/// Types are discovered from the app, or given with `Clap::from_parts`:
+///
+/// ```
+/// use clap_config::CliType;
+/// use std::collections::HashMap;
+///
+/// let mut subcommand = HashMap::new();
+/// subcommand.insert("ids".to_owned(), CliType::Multiple);
+/// subcommand.insert("flag".to_owned(), CliType::Boolean);
+///
+/// let mut args = HashMap::new();
+/// args.insert("subcommand".to_owned(), CliType::Subcommand(subcommand.clone()));
+/// assert_eq!(args["subcommand"], CliType::Subcommand(subcommand));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CliType {
+    /// Option taking several values, collected as an array of all of them,
+    /// split on the value delimiter if there is one.
     Multiple,
+    /// Option taking one value, collected as a string. Absent options are skipped.
     Single,
+    /// Flag given several times, collected as the number of occurrences.
+    /// Flags that weren't given are skipped.
     Count,
+    /// Flag given at most once, collected as `true` when given and skipped otherwise.
     Boolean,
+    /// Subcommand with its own args, collected as a table when it was chosen.
     Subcommand(HashMap<String, CliType>),
+    /// Group of mutually exclusive args, collected as the name of the given one.
+    ///
+    /// Groups are not discovered with clap 3, which doesn't expose them.
+    Group(Vec<String>),
 }
 
-impl From<App<'static, 'static>> for Clap {
-    fn from(app: App<'static, 'static>) -> Clap {
-        Clap::new(app)
+impl CliType {
+    fn new(takes_value: bool, multiple: bool) -> Self {
+        match (takes_value, multiple) {
+            (true, true) => CliType::Multiple,
+            (true, false) => CliType::Single,
+            (false, true) => CliType::Count,
+            (false, false) => CliType::Boolean,
+        }
     }
 }
 
-impl Clap {
-    pub fn new(app: App<'static, 'static>) -> Self {
-        Self::from_matches(Self::get_args_types(&app), app.get_matches())
+/// Which values are collected, see `Clap::user_only` and `Clap::defaults_only`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layer {
+    All,
+    User,
+    Defaults,
+    Changed,
+}
+
+/// Access to parsed arguments that differs between clap versions.
+trait Matches {
+    /// Values of the argument, or `None` if it is absent.
+    fn raw_values(&self, name: &str) -> Option<Vec<&OsStr>>;
+
+    /// How many times the argument was given on the command line.
+    fn occurrences(&self, name: &str) -> u64;
+
+    /// Whether the value of the argument is a clap default, or `None` if clap
+    /// doesn't track it and the static `ArgInfo::has_default` is used.
+    fn is_default(&self, name: &str) -> Option<bool>;
+
+    /// Whether the value of the argument was read from its `env` variable, or `None`
+    /// if clap doesn't track it and the static `ArgInfo::env` is used.
+    fn is_env(&self, name: &str) -> Option<bool>;
+
+    /// How many times a counting flag was given.
+    fn count(&self, name: &str) -> u64;
+
+    /// Value of a boolean flag, or `None` if it was not given.
+    fn flag(&self, name: &str) -> Option<bool>;
+
+    /// Value of a boolean flag that was not given: `false`, or `true` for flags
+    /// that clear it like `ArgAction::SetFalse`.
+    fn flag_default(&self, name: &str) -> bool;
+
+    /// Positions of the values of the argument on the command line, or of its
+    /// occurrences for flags.
+    fn indices(&self, name: &str) -> Vec<usize>;
+}
+
+type KeyMap = dyn Fn(&str) -> Option<String> + Send + Sync;
+type PostProcess = dyn Fn(&mut HashMap<String, Value>) + Send + Sync;
+type ValueMap = dyn Fn(&str) -> Value + Send + Sync;
+type Cache = Mutex<Option<HashMap<String, Value>>>;
+
+/// User supplied closure, shared between clones of `Clap`.
+struct Hook<F: ?Sized>(Arc<F>);
+
+impl<F: ?Sized> Clone for Hook<F> {
+    fn clone(&self) -> Self {
+        Hook(self.0.clone())
     }
+}
 
-    pub fn from_args<I>(app: App<'static, 'static>, args: I) -> Self
-    where
-        I: IntoIterator,
-        I::Item: Into<OsString> + Clone,
-    {
-        Self::from_matches(Self::get_args_types(&app), app.get_matches_from(args))
+impl<F: ?Sized> fmt::Debug for Hook<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
+
+impl<F: ?Sized> Deref for Hook<F> {
+    type Target = F;
+
+    fn deref(&self) -> &F {
+        &self.0
+    }
+}
+
+/// Values remembered by `memoize`, if it is on. Clones start over, as they can be
+/// configured differently before they collect.
+#[derive(Debug, Default)]
+struct Memo(Option<Cache>);
+
+impl Memo {
+    fn reset(&mut self) {
+        if let Some(cache) = &mut self.0 {
+            *cache = Cache::default();
+        }
     }
+}
+
+impl Clone for Memo {
+    fn clone(&self) -> Self {
+        Memo(self.0.as_ref().map(|_| Cache::default()))
+    }
+}
+
+impl Deref for Memo {
+    type Target = Option<Cache>;
+
+    fn deref(&self) -> &Option<Cache> {
+        &self.0
+    }
+}
 
+#[derive(Debug, Clone, Default)]
+struct ArgInfo {
+    delimiter: Option<char>,
+    has_default: bool,
+    default_values: Vec<OsString>,
+    env: bool,
+    short: Option<char>,
+    long: Option<String>,
+    aliases: Vec<String>,
+    possible_values: Vec<String>,
+    hidden: bool,
+    required: bool,
+    subcommands_negate_reqs: bool,
+    index: Option<usize>,
+    path_hint: bool,
+    /// Declaration order among the args and subcommands of the same app.
+    #[cfg_attr(not(feature = "indexmap"), allow(dead_code))]
+    order: usize,
+    help: Option<String>,
+    args: HashMap<String, ArgInfo>,
+}
+
+#[derive(Debug, Clone)]
+struct AppName {
+    name: String,
+    bin_name: Option<String>,
+}
+
+impl Clap {
+    /// Record the chosen subcommand under `field`.
+    ///
+    /// Nested subcommands are joined with the `path_separator`,
+    /// so `app db migrate` records `db.migrate`. Names are converted with `rename_keys`.
     pub fn subcommand_field(mut self, field: &str) -> Self {
         self.subcommand_field = Some(field.to_owned());
         self
     }
 
-    fn get_args_types(app: &App) -> HashMap<String, CliType> {
-        fn convert(name: &str, takes_value: bool, multiple: bool) -> (String, CliType) {
-            (
-                name.to_owned(),
-                match (takes_value, multiple) {
-                    (true, true) => CliType::Multiple,
-                    (true, false) => CliType::Single,
-                    (false, true) => CliType::Count,
-                    (false, false) => CliType::Boolean,
-                },
-            )
-        }
+    /// Record the binary name, see `bin_name`, under `field`.
+    pub fn bin_name_field(mut self, field: &str) -> Self {
+        self.bin_name_field = Some(field.to_owned());
+        self
+    }
 
-        app.p
-            .subcommands
-            .iter()
-            .map(|app| {
-                (
-                    app.p.meta.name.clone(),
-                    CliType::Subcommand(Self::get_args_types(&app)),
-                )
-            })
-            .chain(app.p.opts.iter().map(|opt| {
-                convert(
-                    opt.b.name,
-                    opt.b.settings.is_set(ArgSettings::TakesValue),
-                    opt.b.settings.is_set(ArgSettings::Multiple),
-                )
-            }))
-            .chain(app.p.flags.iter().map(|flag| {
-                convert(
-                    flag.b.name,
-                    flag.b.settings.is_set(ArgSettings::TakesValue),
-                    flag.b.settings.is_set(ArgSettings::Multiple),
-                )
-            }))
-            .chain(app.p.positionals.iter().map(|(_, pos)| {
-                convert(
-                    pos.b.name,
-                    pos.b.settings.is_set(ArgSettings::TakesValue),
-                    pos.b.settings.is_set(ArgSettings::Multiple),
-                )
-            }))
-            .collect()
+    /// Record the chosen subcommand of each level under its own field, inside
+    /// the table of the parent subcommand, e.g. `command` then `action` records
+    /// `command = "db"` and `db.action = "migrate"` for `app db migrate`.
+    ///
+    /// Levels deeper than the given fields reuse the last one.
+    pub fn subcommand_fields(mut self, fields: &[&str]) -> Self {
+        self.subcommand_fields = fields.iter().map(|field| (*field).to_owned()).collect();
+        self
     }
 
-    fn from_matches(args: HashMap<String, CliType>, matches: ArgMatches<'static>) -> Self {
-        Self {
-            args,
-            matches,
-            subcommand_field: None,
-        }
+    /// Record the chosen child of `subcommand` under `field` in its table, instead
+    /// of the field `subcommand_fields` gives for that level.
+    pub fn subcommand_field_for(mut self, subcommand: &str, field: &str) -> Self {
+        self.subcommand_field_for
+            .insert(subcommand.to_owned(), field.to_owned());
+        self
     }
-}
 
-impl Source for Clap {
-    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
-        Box::new((*self).clone())
+    /// Record `name` in the `subcommand_field` when no subcommand was given.
+    pub fn default_subcommand(mut self, name: &str) -> Self {
+        self.default_subcommand = Some(name.to_owned());
+        self
     }
 
-    fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
-        fn extract_matches(
-            matches: &ArgMatches,
-            args: &HashMap<String, CliType>,
-        ) -> HashMap<String, Value> {
-            args.into_iter()
-                .filter_map(|(name, tpe)| {
-                    let conf_name = name.clone();
-                    match tpe {
-                        CliType::Multiple => matches.values_of(name).map(|values| {
-                            (conf_name, Value::new(None, values.collect::<Vec<_>>()))
-                        }),
-                        CliType::Single => matches
-                            .value_of(name)
-                            .map(|value| (conf_name, Value::new(None, value))),
-                        CliType::Count => Some((
-                            conf_name,
-                            Value::new(None, matches.occurrences_of(name) as i64),
-                        )),
-                        CliType::Boolean => {
-                            Some((conf_name, Value::new(None, matches.is_present(name))))
-                        }
-                        CliType::Subcommand(subargs) => {
-                            matches.subcommand_matches(name).map(|submatches| {
-                                (
-                                    conf_name,
-                                    Value::new(None, extract_matches(submatches, subargs)),
-                                )
-                            })
-                        }
-                    }
-                })
-                .collect()
-        }
+    /// Emit option values that look like numbers as numeric values.
+    ///
+    /// Each value is parsed as `i64` first, then as a finite `f64`; anything else
+    /// is kept as a string, including integers that don't fit an `i64` such as
+    /// `u64::MAX`, which a float couldn't hold exactly. Multiple values are coerced
+    /// only if all of them are numbers, and all to the same type: `["1", "1.5"]`
+    /// becomes floats.
+    pub fn coerce_numbers(mut self, coerce: bool) -> Self {
+        self.coerce_numbers = coerce;
+        self
+    }
 
-        let mut matches = extract_matches(&self.matches, &self.args);
+    /// Emit single option values like `on` or `no` as booleans.
+    ///
+    /// `true`, `yes`, `on`, `1` and `false`, `no`, `off`, `0` are recognized in any case.
+    /// With `coerce_numbers`, numbers are coerced first, so `1` stays a number.
+    /// Multiple values become booleans if all of them are and they are not all numbers.
+    pub fn coerce_bools(mut self, coerce: bool) -> Self {
+        self.coerce_bools = coerce;
+        self
+    }
 
-        if let (Some(subcommand_field), Some(subcommand)) =
-            (&self.subcommand_field, self.matches.subcommand_name())
-        {
-            matches.insert(subcommand_field.clone(), Value::new(None, subcommand));
-        }
+    /// Trim whitespace around option values. With `drop_empty`, values that are
+    /// empty after trimming are skipped.
+    pub fn trim_values(mut self, trim: bool) -> Self {
+        self.trim_values = trim;
+        self
+    }
 
-        Ok(matches)
+    /// Skip values of `arg` equal to `sentinel`, e.g. `--port 0` meaning unset,
+    /// so a value from a file is used instead.
+    pub fn skip_if_value(mut self, arg: &str, sentinel: &str) -> Self {
+        self.sentinels.insert(arg.to_owned(), sentinel.to_owned());
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use clap::{App, Arg};
-    use serde_derive::Deserialize;
+    /// Normalize the value of the single value option `arg` as a path, e.g. expand
+    /// `~/config.toml` to the home directory, and collect it as a string.
+    pub fn as_path(mut self, arg: &str, opts: PathOpts) -> Self {
+        self.paths.insert(arg.to_owned(), opts);
+        self
+    }
 
-    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
-    #[serde(default)]
-    pub struct Config {
-        pub format: Option<String>,
-        pub verbosity: usize,
-        pub subcommand: Option<SubConfig>,
-        pub mode: Option<String>,
+    /// Normalize single value options with a path `ValueHint`, e.g. `FilePath`, as if
+    /// they were given to `as_path` with tilde expansion. `as_path` takes precedence.
+    #[cfg(any(feature = "clap3", feature = "clap4"))]
+    pub fn respect_value_hints(mut self, respect: bool) -> Self {
+        self.respect_value_hints = respect;
+        self
     }
 
-    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
-    #[serde(default)]
-    pub struct SubConfig {
-        pub ids: Vec<u32>,
-        pub flag: bool,
+    /// Skip empty option values, so e.g. `--name ""` doesn't override
+    /// a value from a file. Only empty elements of multiple values are dropped.
+    pub fn drop_empty(mut self, drop: bool) -> Self {
+        self.drop_empty = drop;
+        self
     }
 
-    fn new_app() -> App<'static, 'static> {
-        App::new("app")
-            .arg(
-                Arg::with_name("format")
-                    .takes_value(true)
-                    .short("f")
-                    .long("format"),
-            )
-            .arg(
-                Arg::with_name("verbosity")
-                    .short("v")
-                    .long("verbose")
-                    .multiple(true),
-            )
-            .subcommand(
-                App::new("subcommand")
-                    .arg(Arg::with_name("flag").short("F").long("flag"))
-                    .arg(
-                        Arg::with_name("ids")
-                            .short("i")
-                            .long("id")
-                            .required(true)
-                            .takes_value(true)
-                            .multiple(true),
-                    ),
-            )
+    /// Replace values of args with possible values by the declared choice
+    /// they match ignoring ASCII case, e.g. `JSON` by `json`.
+    pub fn canonicalize_choices(mut self, canonicalize: bool) -> Self {
+        self.canonicalize_choices = canonicalize;
+        self
     }
 
-    fn new_clap_config<I>(args: I) -> Clap
+    /// Convert the case of every collected key, including keys nested under subcommands.
+    pub fn rename_keys(mut self, case: KeyCase) -> Self {
+        self.key_case = case;
+        self
+    }
+
+    /// Collect `arg` under `key`, as is, instead of its name.
+    pub fn rename(mut self, arg: &str, key: &str) -> Self {
+        self.renames.insert(arg.to_owned(), key.to_owned());
+        self
+    }
+
+    /// Match arg names in `get` and `rename` ignoring ASCII case, so `get("Format")`
+    /// finds the `format` arg, and give `known_keys` in lowercase. This only affects
+    /// these helpers, the collected keys are unchanged.
+    pub fn case_insensitive_lookup(mut self, insensitive: bool) -> Self {
+        self.case_insensitive_lookup = insensitive;
+        self
+    }
+
+    /// Collect the last top level positional, e.g. the one capturing everything
+    /// after `--` with `TrailingVarArg`, under `key` like `rename`.
+    /// Does nothing if the app has no positionals.
+    pub fn trailing_key(self, key: &str) -> Self {
+        let trailing = self
+            .info
+            .iter()
+            .filter_map(|(name, info)| Some((info.index?, name)))
+            .max()
+            .map(|(_, name)| name.clone());
+        match trailing {
+            Some(name) => self.rename(&name, key),
+            None => self,
+        }
+    }
+
+    /// Rename or drop (by returning `None`) each arg before it is collected.
+    ///
+    /// The closure gets the arg name, both for top level and subcommand args,
+    /// and runs before `rename_keys`.
+    pub fn map_key<F>(mut self, map: F) -> Self
     where
-        I: IntoIterator,
-        I::Item: Into<OsString> + Clone,
+        F: Fn(&str) -> Option<String> + Send + Sync + 'static,
     {
-        Clap::from_args(new_app(), args).subcommand_field("mode")
+        self.key_map = Some(Hook(Arc::new(map)));
+        self
     }
 
-    fn test_clap_with_args(args: Vec<&str>, expected: Config) {
-        let mut conf = config::Config::new();
-        let clap = new_clap_config(args);
+    /// Convert values of `arg` with `map` instead of collecting them as strings,
+    /// e.g. to parse `--size 1k` into the number 1024.
+    ///
+    /// Multiple values are converted one by one.
+    pub fn map_value<F>(mut self, arg: &str, map: F) -> Self
+    where
+        F: Fn(&str) -> Value + Send + Sync + 'static,
+    {
+        self.value_maps.insert(arg.to_owned(), Hook(Arc::new(map)));
+        self
+    }
 
-        log::debug!("CLAP: {:?}", clap);
+    /// Fail `collect` when several args end up under the same key after `rename`,
+    /// `map_key`, `rename_keys` or `key_from_long` and were collected with different
+    /// values, instead of keeping one of them. Args that agree or weren't given pass.
+    pub fn strict_keys(mut self, strict: bool) -> Self {
+        self.strict_keys = strict;
+        self
+    }
 
-        conf.merge(clap).unwrap();
-        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    /// Tag all of the collected values with `origin`, e.g. `"command line"`,
+    /// which `config` mentions in its errors.
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.origin = Some(origin.to_owned());
+        self
     }
 
-    #[test]
-    fn test_clap() {
-        env_logger::init();
+    /// Change the collected values with `process` before they are returned from `collect`,
+    /// after all the other options were applied.
+    pub fn post_process<F>(mut self, process: F) -> Self
+    where
+        F: Fn(&mut HashMap<String, Value>) + Send + Sync + 'static,
+    {
+        self.post_process = Some(Hook(Arc::new(process)));
+        self
+    }
 
-        test_clap_with_args(
-            vec![
-                "myprog",
-                "-vvv",
-                "--format=json",
-                "subcommand",
-                "-i1",
-                "-i2",
-                "-i3",
-            ],
-            Config {
-                format: Some("json".into()),
-                verbosity: 3,
-                subcommand: Some(SubConfig {
-                    ids: vec![1, 2, 3],
-                    flag: false,
-                }),
-                mode: Some("subcommand".into()),
-            },
+    /// Treat the `path_separator` in (renamed) keys as nesting and collect them into tables,
+    /// so `db.host` and `db.port` end up in a single `db` table.
+    pub fn nested_keys(mut self, nested: bool) -> Self {
+        self.nested_keys = nested;
+        self
+    }
+
+    /// Skip values that come from clap's `default_value` rather than the command line,
+    /// so they don't override lower-priority sources.
+    pub fn ignore_defaults(mut self, ignore: bool) -> Self {
+        self.ignore_defaults = ignore;
+        self
+    }
+
+    /// Collect only values given on the command line, skipping clap defaults
+    /// and values read from `env`. Pairs with `defaults_only` as the top layer.
+    pub fn user_only(mut self) -> Self {
+        self.layer = Layer::User;
+        self
+    }
+
+    /// Collect only clap `default_value`s of args that were not given,
+    /// to merge as the lowest layer under config files.
+    pub fn defaults_only(mut self) -> Self {
+        self.layer = Layer::Defaults;
+        self
+    }
+
+    /// Values given on the command line that differ from the clap defaults,
+    /// e.g. to write back a minimal config of the user overrides.
+    /// Like `user_only`, but also skips values given the same as the default.
+    pub fn diff_from_defaults(&self) -> HashMap<String, Value> {
+        let mut clap = self.clone();
+        clap.layer = Layer::Changed;
+        clap.to_value_map().unwrap_or_default()
+    }
+
+    /// Skip values that clap read from an arg's `env` variable rather than the
+    /// command line, leaving the environment to a `config::Environment` source.
+    pub fn ignore_env_values(mut self, ignore: bool) -> Self {
+        self.ignore_env_values = ignore;
+        self
+    }
+
+    /// Put every collected key under `prefix`, so `format` becomes `prefix.format`
+    /// (joined with the `path_separator`).
+    ///
+    /// Subcommand tables and the `subcommand_field` are prefixed as well.
+    pub fn key_prefix(mut self, prefix: &str) -> Self {
+        self.key_prefix = Some(prefix.to_owned());
+        self
+    }
+
+    /// Never collect the given args, at any subcommand level.
+    pub fn exclude(mut self, names: &[&str]) -> Self {
+        self.exclude
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Collect only the given args and nothing else.
+    ///
+    /// A listed subcommand is collected with all of its args; other subcommands
+    /// only contribute their listed args.
+    pub fn include_only(mut self, names: &[&str]) -> Self {
+        self.include_only = Some(names.iter().map(|name| (*name).to_owned()).collect());
+        self
+    }
+
+    /// Split off the given args into a separate low-priority source.
+    ///
+    /// A `Source` can't see what other sources already set, so precedence comes
+    /// from the merge order: merge the first returned source before config files
+    /// and the second one, with the remaining args, after them.
+    ///
+    /// ```
+    /// # #[cfg(feature = "clap2")]
+    /// # fn main() {
+    /// # use clap::{App, Arg};
+    /// # use clap_config::Clap;
+    /// let app = App::new("app")
+    ///     .arg(Arg::with_name("format").long("format").takes_value(true))
+    ///     .arg(Arg::with_name("fallback").long("fallback").takes_value(true));
+    /// let (weak, strong) = Clap::from_args(app, vec!["myprog", "--fallback", "cli"])
+    ///     .weak(&["fallback"]);
+    ///
+    /// let mut config = config::Config::new();
+    /// config
+    ///     .merge(weak).unwrap()
+    ///     .merge(config::File::from_str("fallback = \"file\"", config::FileFormat::Toml)).unwrap()
+    ///     .merge(strong).unwrap();
+    /// assert_eq!(config.get_str("fallback").unwrap(), "file");
+    /// # }
+    /// # #[cfg(not(feature = "clap2"))]
+    /// # fn main() {}
+    /// ```
+    pub fn weak(self, names: &[&str]) -> (Self, Self) {
+        (self.clone().include_only(names), self.exclude(names))
+    }
+
+    /// Don't collect args that are hidden from the help message.
+    pub fn skip_hidden(mut self, skip: bool) -> Self {
+        self.skip_hidden = skip;
+        self
+    }
+
+    /// Keep option values that are not valid UTF-8, converting them lossily,
+    /// instead of dropping them.
+    pub fn os_values(mut self, os_values: bool) -> Self {
+        self.os_values = os_values;
+        self
+    }
+
+    /// Collect the counting flag `arg` as the level named by its count, `levels[n]`
+    /// for `n` occurrences and the last level for more, e.g. `-vv` as `levels[2]`.
+    ///
+    /// Like other flags, the key is skipped when the flag is absent: `levels[0]` is
+    /// never collected, so a level from a file is kept.
+    pub fn count_to_levels(mut self, arg: &str, levels: &[&str]) -> Self {
+        self.count_levels.insert(
+            arg.to_owned(),
+            levels.iter().map(|level| (*level).to_owned()).collect(),
+        );
+        self
+    }
+
+    /// Collect the given counting flags as `true` instead of the number of occurrences.
+    pub fn count_as_bool(mut self, names: &[&str]) -> Self {
+        self.count_as_bool
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Collect subcommand args into the parent table instead of a table named
+    /// after the subcommand. Subcommand args win over parent args with the same key.
+    pub fn flatten_subcommands(mut self, flatten: bool) -> Self {
+        self.flatten_subcommands = flatten;
+        self
+    }
+
+    /// Use the long flag name (`--verbose`) instead of the arg name as the key,
+    /// when the arg has one. The long name is what `map_key` receives then.
+    pub fn key_from_long(mut self, from_long: bool) -> Self {
+        self.key_from_long = from_long;
+        self
+    }
+
+    /// Also collect each value under the visible aliases of its arg,
+    /// so `--color` with alias `colour` fills both `color` and `colour`.
+    ///
+    /// Every alias adds a key, which can clutter configs that are serialized back.
+    pub fn emit_aliases(mut self, emit: bool) -> Self {
+        self.emit_aliases = emit;
+        self
+    }
+
+    /// Also collect the value of `arg` under each of `extra_keys`, as is,
+    /// e.g. for consumers still reading an old name.
+    pub fn alias_key(mut self, arg: &str, extra_keys: &[&str]) -> Self {
+        self.alias_keys
+            .entry(arg.to_owned())
+            .or_default()
+            .extend(extra_keys.iter().map(|key| (*key).to_owned()));
+        self
+    }
+
+    /// Separator used to join and split key paths: subcommand chains in the
+    /// `subcommand_field`, `nested_keys` and the `key_prefix`. Defaults to `.`.
+    pub fn path_separator(mut self, separator: &str) -> Self {
+        self.path_separator = separator.to_owned();
+        self
+    }
+
+    /// Collect the arg as `ty` instead of the type inferred from its clap settings.
+    /// Args with that name in subcommands are overridden too.
+    pub fn force_type(mut self, arg: &str, ty: CliType) -> Self {
+        fn force(args: &mut HashMap<String, CliType>, arg: &str, ty: &CliType) {
+            for (name, tpe) in args.iter_mut() {
+                match tpe {
+                    CliType::Subcommand(subargs) => force(subargs, arg, ty),
+                    _ if name == arg => *tpe = ty.clone(),
+                    _ => {}
+                }
+            }
+        }
+
+        force(&mut self.args, arg, &ty);
+        self
+    }
+
+    /// Drop repeated values of the given multiple value args, keeping the first occurrence.
+    pub fn dedup_multiple(mut self, names: &[&str]) -> Self {
+        self.dedup_multiple
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Collect the multiple values of `arg` as a table of `key<separator>value`
+    /// pairs, e.g. `-D a=1 -D b=2` with `'='`. Values without the separator are skipped.
+    pub fn collect_as_map(mut self, arg: &str, separator: char) -> Self {
+        self.map_args.insert(arg.to_owned(), separator);
+        self
+    }
+
+    /// Collect the values of `arg` as arrays of `n` values each, e.g. for
+    /// `number_of_values(2)` so `--point 1 2 --point 3 4` becomes `[[1, 2], [3, 4]]`.
+    /// The last array is shorter if the values don't divide evenly.
+    pub fn collect_as_tuples(mut self, arg: &str, n: usize) -> Self {
+        self.tuples.insert(arg.to_owned(), n.max(1));
+        self
+    }
+
+    /// Collect boolean flags that weren't given as their default instead of skipping
+    /// them, `false` or `true` for `SetFalse` flags, e.g. for a complete config snapshot.
+    /// Not with `user_only`.
+    pub fn emit_all_booleans(mut self, emit: bool) -> Self {
+        self.emit_all_booleans = emit;
+        self
+    }
+
+    /// Collect the given single value args as null when they are not given,
+    /// instead of skipping them.
+    pub fn emit_null_for(mut self, names: &[&str]) -> Self {
+        self.emit_null
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Collect the flag `arg` as the string `present` when it is given, and as
+    /// `absent` otherwise. Without `absent` the flag is skipped when not given.
+    pub fn bool_as_string(mut self, arg: &str, present: &str, absent: Option<&str>) -> Self {
+        self.bool_strings.insert(
+            arg.to_owned(),
+            (present.to_owned(), absent.map(str::to_owned)),
+        );
+        self
+    }
+
+    /// Collect the pair of flags `enable` and `disable`, e.g. `--foo` and `--no-foo`,
+    /// as one boolean under `key`: the one given last wins, and the key is skipped
+    /// if neither was given. Only for top level flags.
+    pub fn negatable(mut self, key: &str, enable: &str, disable: &str) -> Self {
+        self.negatables
+            .push((key.to_owned(), enable.to_owned(), disable.to_owned()));
+        self
+    }
+
+    /// Sort values of the given multiple value args, numerically if all of them
+    /// are numbers and `coerce_numbers` is on, lexicographically otherwise.
+    pub fn sort_multiple(mut self, names: &[&str]) -> Self {
+        self.sort_multiple
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Append values of the given multiple value args to the values already in the
+    /// config instead of replacing them, when merged with `merge_into`.
+    ///
+    /// A `Source` can't see the config it is merged into, so `merge_into` reads the
+    /// current values first. Merging with `config.merge` keeps replacing them.
+    pub fn append_multiples(mut self, names: &[&str]) -> Self {
+        self.append_multiples
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Next to each collected option value, emit how many times the option was given
+    /// on the command line under the key with `suffix` appended, e.g. `format_count`
+    /// with the suffix `_count`. The count is `0` for values from defaults or `env`.
+    pub fn with_occurrence_counts(mut self, enabled: bool, suffix: &str) -> Self {
+        self.occurrence_counts = enabled.then(|| suffix.to_owned());
+        self
+    }
+
+    /// Remember what the first `collect` returns and return it again on later calls,
+    /// as `Config` collects every source again on each `merge`.
+    ///
+    /// Options changed after the first `collect` are not picked up then. Clones
+    /// remember their own values, so they can be configured differently.
+    pub fn memoize(mut self, memoize: bool) -> Self {
+        self.cache = Memo(memoize.then(Cache::default));
+        self
+    }
+
+    /// Collect the values right away and return them from every later `collect`,
+    /// instead of walking the matches again. Call it after all the other options.
+    pub fn precompute(mut self) -> Self {
+        let values = self.to_value_map().ok();
+        self.cache = Memo(Some(Mutex::new(values)));
+        self
+    }
+
+    /// Pass each collected key and value to `visitor`, without copying them out of
+    /// the cache when the values are memoized.
+    pub fn collect_ref<F>(&self, mut visitor: F) -> Result<(), ConfigError>
+    where
+        F: FnMut(&str, &Value),
+    {
+        let mut visit = |values: &HashMap<String, Value>| {
+            for (key, value) in values {
+                visitor(key, value);
+            }
+        };
+        match &*self.cache {
+            Some(cache) => {
+                let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+                let values = match &mut *cache {
+                    Some(values) => values,
+                    cache @ None => cache.insert(self.to_value_map()?),
+                };
+                visit(values);
+            }
+            None => visit(&self.to_value_map()?),
+        }
+        Ok(())
+    }
+
+    /// Values this source contributes to a `Config`, the same as `Source::collect`.
+    pub fn to_value_map(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        if self.strict_keys {
+            for source in self.sources() {
+                source.check_keys(
+                    &source.matches,
+                    &source.args,
+                    &source.info,
+                    source.include_only.as_ref(),
+                )?;
+            }
+        }
+        let mut matches = self.extract_matches(
+            &self.matches,
+            &self.args,
+            &self.info,
+            self.include_only.as_ref(),
+        );
+
+        for (key, enable, disable) in &self.negatables {
+            for name in [enable, disable] {
+                if let Some(key) = self.key(name, self.info.get(name)) {
+                    matches.remove(&key);
+                }
+            }
+            let last = |name: &str| {
+                (self.matches.occurrences(name) > 0)
+                    .then(|| self.matches.indices(name).last().copied())
+                    .flatten()
+            };
+            let enabled = match (last(enable), last(disable)) {
+                (Some(enable), Some(disable)) => enable > disable,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => continue,
+            };
+            if self.layer != Layer::Defaults {
+                self.insert(&mut matches, key, Value::new(None, enabled));
+            }
+        }
+
+        let subcommand = match self.subcommand_path() {
+            Some(subcommand) => Some(subcommand).filter(|_| self.layer != Layer::Defaults),
+            None => self.default_subcommand.clone(),
+        };
+        if let (Some(subcommand_field), Some(subcommand)) = (&self.subcommand_field, subcommand) {
+            self.insert(&mut matches, subcommand_field, Value::new(None, subcommand));
+        }
+        if let (Some(field), Some(bin_name)) = (&self.bin_name_field, self.bin_name()) {
+            if self.layer != Layer::Defaults {
+                self.insert(&mut matches, field, Value::new(None, bin_name));
+            }
+        }
+        let per_level = !self.subcommand_fields.is_empty() || !self.subcommand_field_for.is_empty();
+        if per_level && self.layer != Layer::Defaults {
+            self.insert_subcommand_fields(&mut matches, &self.matches, &self.info, None, 0);
+        }
+
+        if let Some(prefix) = &self.key_prefix {
+            matches = matches
+                .into_iter()
+                .map(|(key, value)| (format!("{}{}{}", prefix, self.path_separator, key), value))
+                .collect();
+        }
+
+        for (key, base) in &self.append_base {
+            self.append(&mut matches, key, base);
+        }
+
+        if let Some(origin) = &self.origin {
+            matches = matches
+                .into_iter()
+                .map(|(key, value)| (key, with_origin(value, origin)))
+                .collect();
+        }
+
+        if let Some(post_process) = &self.post_process {
+            post_process(&mut matches);
+        }
+
+        for source in &self.combined {
+            for (key, value) in source.to_value_map()? {
+                if self.strict_keys && matches.contains_key(&key) {
+                    return Err(ConfigError::Message(format!(
+                        "`{}` is collected by several combined sources",
+                        key
+                    )));
+                }
+                matches.insert(key, value);
+            }
+        }
+
+        Ok(matches)
+    }
+
+    /// Collect several sources as one, e.g. for apps contributed by plugins.
+    ///
+    /// Each source keeps its own options. When several sources collect the
+    /// same key the later one wins, or `collect` fails if `strict_keys` is
+    /// set on the first source. Helpers like `get`, `known_keys` and `to_argv`
+    /// look at all of the sources.
+    pub fn combine<I>(sources: I) -> Self
+    where
+        I: IntoIterator<Item = Clap>,
+    {
+        let mut sources = sources.into_iter();
+        let mut clap = sources
+            .next()
+            .unwrap_or_else(|| Self::from_parts(HashMap::new(), Default::default()));
+        // Keep a single level, so `sources` sees every combined source.
+        for mut source in sources {
+            let nested = std::mem::take(&mut source.combined);
+            clap.combined.push(source);
+            clap.combined.extend(nested);
+        }
+        clap
+    }
+
+    /// Collected values as a flat map of strings, for backends without nesting.
+    ///
+    /// Keys inside subcommand and nested tables are joined with the `path_separator`,
+    /// e.g. `subcommand.ids`, and multiple values are joined with commas. Arrays of
+    /// arrays, e.g. from `collect_as_tuples`, are flattened by index, e.g. `point.0`
+    /// and `point.1`. Nulls from `emit_null_for` become empty strings.
+    pub fn collect_flat(&self) -> Result<HashMap<String, String>, ConfigError> {
+        fn flatten(
+            key: String,
+            separator: &str,
+            value: Value,
+            flat: &mut HashMap<String, String>,
+        ) -> Result<(), ConfigError> {
+            // `config` doesn't export its `ValueKind`, so probe the value instead.
+            let nested = |value: &Value| {
+                value.clone().into_table().is_ok() || value.clone().into_array().is_ok()
+            };
+            if value.kind == Value::new(None, None::<String>).kind {
+                flat.insert(key, String::new());
+            } else if let Ok(table) = value.clone().into_table() {
+                for (name, value) in table {
+                    flatten(
+                        format!("{}{}{}", key, separator, name),
+                        separator,
+                        value,
+                        flat,
+                    )?;
+                }
+            } else if let Ok(array) = value.clone().into_array() {
+                if array.iter().any(nested) {
+                    for (index, value) in array.into_iter().enumerate() {
+                        flatten(
+                            format!("{}{}{}", key, separator, index),
+                            separator,
+                            value,
+                            flat,
+                        )?;
+                    }
+                } else {
+                    let values: Result<Vec<String>, _> =
+                        array.into_iter().map(Value::into_str).collect();
+                    flat.insert(key, values?.join(","));
+                }
+            } else {
+                flat.insert(key, value.into_str()?);
+            }
+            Ok(())
+        }
+
+        let mut flat = HashMap::new();
+        for (key, value) in self.collect()? {
+            flatten(key, &self.path_separator, value, &mut flat)?;
+        }
+        Ok(flat)
+    }
+
+    /// Build a source from matches parsed elsewhere and a map of arg types,
+    /// for when the app is no longer available.
+    ///
+    /// ```
+    /// use clap_config::{Clap, CliType};
+    /// use config::Source;
+    /// use std::collections::HashMap;
+    ///
+    /// let mut args = HashMap::new();
+    /// args.insert("format".to_owned(), CliType::Single);
+    ///
+    /// let clap = Clap::from_parts(args, Default::default());
+    /// assert!(clap.collect().unwrap().is_empty());
+    /// ```
+    pub fn from_parts(args: HashMap<String, CliType>, matches: ArgMatches) -> Self {
+        Self::from_matches(args, HashMap::new(), None, matches)
+    }
+
+    /// Name of the app the args were read from, `None` for `from_parts`.
+    pub fn app_name(&self) -> Option<&str> {
+        self.app_name.as_ref().map(|app| app.name.as_str())
+    }
+
+    /// Help of the app rendered by `with_app`, e.g. to show it again when the
+    /// config fails to validate.
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// Binary name of the app, as set on it or else taken from the first arg.
+    pub fn bin_name(&self) -> Option<&str> {
+        self.app_name
+            .as_ref()
+            .and_then(|app| app.bin_name.as_deref())
+    }
+
+    /// Top level args and subcommands with their types.
+    pub fn arg_types(&self) -> impl Iterator<Item = (&str, &CliType)> {
+        self.sources()
+            .flat_map(|source| &source.args)
+            .map(|(name, tpe)| (name.as_str(), tpe))
+    }
+
+    /// This source and the ones combined with it by `combine`, in merge order.
+    fn sources(&self) -> impl DoubleEndedIterator<Item = &Clap> {
+        std::iter::once(self).chain(&self.combined)
+    }
+
+    /// All args with their types, including args of subcommands as paths joined
+    /// with the `path_separator`, e.g. `subcommand.ids`. Sorted by path.
+    pub fn arg_types_recursive(&self) -> Vec<(String, &CliType)> {
+        fn walk<'a>(
+            prefix: &str,
+            separator: &str,
+            args: &'a HashMap<String, CliType>,
+            types: &mut Vec<(String, &'a CliType)>,
+        ) {
+            for (name, tpe) in args {
+                let path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}{}{}", prefix, separator, name)
+                };
+                if let CliType::Subcommand(subargs) = tpe {
+                    walk(&path, separator, subargs, types);
+                }
+                types.push((path, tpe));
+            }
+        }
+
+        let mut types = Vec::new();
+        for source in self.sources() {
+            walk("", &source.path_separator, &source.args, &mut types);
+        }
+        types.sort_by(|(a, _), (b, _)| a.cmp(b));
+        types
+    }
+
+    /// Keys that args can be collected under with the current settings, whether
+    /// they were given or not. Keys of subcommand args are paths joined with the
+    /// `path_separator`, e.g. `subcommand.ids`.
+    pub fn known_keys(&self) -> HashSet<String> {
+        let mut keys: HashSet<String> =
+            self.arg_keys().into_iter().map(|(_, key, _)| key).collect();
+        if let Some(field) = &self.subcommand_field {
+            keys.insert(match &self.key_prefix {
+                Some(prefix) => format!("{}{}{}", prefix, self.path_separator, field),
+                None => field.clone(),
+            });
+        }
+        if self.case_insensitive_lookup {
+            keys = keys
+                .into_iter()
+                .map(|key| key.to_ascii_lowercase())
+                .collect();
+        }
+        for source in &self.combined {
+            keys.extend(source.known_keys());
+        }
+        keys
+    }
+
+    /// Help text of the arg collected under `key`, one of the `known_keys`,
+    /// e.g. to document a config template.
+    pub fn help_for(&self, key: &str) -> Option<&str> {
+        self.sources().rev().find_map(|source| {
+            source
+                .arg_keys()
+                .into_iter()
+                .find(|(_, arg_key, _)| arg_key == key)?
+                .2?
+                .help
+                .as_deref()
+        })
+    }
+
+    /// Values of the top level positional args by arg name, in the order of
+    /// their index, e.g. to rebuild the command line.
+    pub fn positionals_in_order(&self) -> Vec<(String, Value)> {
+        let mut ordered = Vec::new();
+        for source in self.sources() {
+            let mut positionals: Vec<(usize, &String)> = source
+                .info
+                .iter()
+                .filter_map(|(name, info)| Some((info.index?, name)))
+                .collect();
+            positionals.sort();
+            ordered.extend(positionals.into_iter().filter_map(|(_, name)| {
+                let values = source.strings(source.matches.raw_values(name)?);
+                let value = match source.args.get(name)? {
+                    CliType::Multiple => source.values(values.iter().map(AsRef::as_ref).collect()),
+                    _ => source.single_value(values.first()?),
+                };
+                Some((name.clone(), value))
+            }));
+        }
+        ordered
+    }
+
+    /// Args given on the command line, without the binary name, that reproduce
+    /// the invocation when parsed by the same app. Options and flags come first,
+    /// sorted by name, then positionals and the chosen subcommand with its args.
+    /// Positionals follow a `--` if any of them starts with `-`.
+    /// Defaults and env values are left out, args of `combine`d sources follow in order.
+    pub fn to_argv(&self) -> Vec<String> {
+        fn walk(
+            clap: &Clap,
+            matches: &ArgMatches,
+            args: &HashMap<String, CliType>,
+            info: &HashMap<String, ArgInfo>,
+            argv: &mut Vec<String>,
+        ) {
+            let mut names: Vec<&String> = args.keys().collect();
+            names.sort();
+            let (positionals, names): (Vec<_>, Vec<_>) = names
+                .into_iter()
+                .partition(|name| info.get(*name).is_some_and(|info| info.index.is_some()));
+            let mut positionals: Vec<(Option<usize>, &String)> = positionals
+                .into_iter()
+                .map(|name| (info.get(name).and_then(|info| info.index), name))
+                .collect();
+            positionals.sort();
+
+            for name in names {
+                if matches.occurrences(name) == 0 {
+                    continue;
+                }
+                let info = info.get(name);
+                let long = info.and_then(|info| info.long.as_deref());
+                let short = info.and_then(|info| info.short);
+                let flag = || match (long, short) {
+                    (Some(long), _) => format!("--{}", long),
+                    (None, Some(short)) => format!("-{}", short),
+                    (None, None) => format!("--{}", name),
+                };
+                match &args[name] {
+                    CliType::Multiple | CliType::Single => {
+                        let values: Vec<String> = clap
+                            .strings(matches.raw_values(name).unwrap_or_default())
+                            .into_iter()
+                            .map(Cow::into_owned)
+                            .collect();
+                        let occurrences = match info.and_then(|info| info.delimiter) {
+                            // clap split the values, so give them back as one occurrence.
+                            Some(delimiter) => vec![vec![values.join(&delimiter.to_string())]],
+                            None => occurrence_values(matches.indices(name), values),
+                        };
+                        for values in occurrences {
+                            match (long, values.as_slice()) {
+                                (Some(long), [value]) => argv.push(format!("--{}={}", long, value)),
+                                _ => {
+                                    argv.push(flag());
+                                    argv.extend(values);
+                                }
+                            }
+                        }
+                    }
+                    CliType::Count => match short {
+                        Some(short) => argv.push(format!(
+                            "-{}",
+                            short.to_string().repeat(matches.count(name) as usize)
+                        )),
+                        None => argv.extend((0..matches.count(name)).map(|_| flag())),
+                    },
+                    // Given flags, including clap 4 `SetFalse` ones that collect `false`.
+                    CliType::Boolean if matches.flag(name).is_some() => argv.push(flag()),
+                    CliType::Boolean | CliType::Group(_) | CliType::Subcommand(_) => {}
+                }
+            }
+            let values: Vec<String> = positionals
+                .into_iter()
+                .filter(|(_, name)| matches.occurrences(name) > 0)
+                .flat_map(|(_, name)| clap.strings(matches.raw_values(name).unwrap_or_default()))
+                .map(Cow::into_owned)
+                .collect();
+            if values.iter().any(|value| value.starts_with('-')) {
+                argv.push("--".to_owned());
+            }
+            argv.extend(values);
+            if let Some(name) = matches.subcommand_name() {
+                if let (Some(CliType::Subcommand(subargs)), Some(submatches)) =
+                    (args.get(name), matches.subcommand_matches(name))
+                {
+                    argv.push(name.to_owned());
+                    walk(
+                        clap,
+                        submatches,
+                        subargs,
+                        info.get(name).map_or(&HashMap::new(), |info| &info.args),
+                        argv,
+                    );
+                }
+            }
+        }
+
+        let mut argv = Vec::new();
+        for source in self.sources() {
+            walk(
+                source,
+                &source.matches,
+                &source.args,
+                &source.info,
+                &mut argv,
+            );
+        }
+        argv
+    }
+
+    /// Parse the value of a top level single value arg, or `None` if it is absent,
+    /// not a single value arg or doesn't parse.
+    pub fn get<T: FromStr>(&self, name: &str) -> Option<T> {
+        // Like in `collect`, later combined sources win.
+        self.sources().rev().find_map(|source| {
+            let (name, tpe) = source.lookup(&source.args, name)?;
+            match tpe {
+                CliType::Single => source
+                    .strings(source.matches.raw_values(name)?)
+                    .first()?
+                    .parse()
+                    .ok(),
+                _ => None,
+            }
+        })
+    }
+
+    /// Keys that `collect` emits with the current settings, sorted.
+    ///
+    /// Keys inside subcommand and nested tables are given as paths joined with the
+    /// `path_separator`, e.g. `subcommand.ids`.
+    pub fn present_keys(&self) -> Vec<String> {
+        fn walk(
+            prefix: &str,
+            separator: &str,
+            map: HashMap<String, Value>,
+            keys: &mut Vec<String>,
+        ) {
+            for (key, value) in map {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}{}{}", prefix, separator, key)
+                };
+                match value.clone().into_table() {
+                    Ok(table) if !table.is_empty() => walk(&key, separator, table, keys),
+                    _ => keys.push(key),
+                }
+            }
+        }
+
+        let mut keys = Vec::new();
+        walk(
+            "",
+            &self.path_separator,
+            self.to_value_map().unwrap_or_default(),
+            &mut keys,
+        );
+        keys.sort();
+        keys
+    }
+
+    /// Deserialize the collected values on their own, without other sources.
+    ///
+    /// This goes through a `config::Config`, so dotted keys are nested the same way.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, ConfigError> {
+        let mut config = config::Config::new();
+        config.merge(self.clone())?;
+        config.try_into()
+    }
+
+    /// Override the fields of `base` with the collected values, e.g. to apply the
+    /// command line to a config that is already loaded, and return the result.
+    pub fn apply_to<T: Serialize + DeserializeOwned>(&self, base: T) -> Result<T, ConfigError> {
+        // `try_from` sets the values as overrides, so merge them as a source instead.
+        let mut config = config::Config::new();
+        config.merge(config::Config::try_from(&base)?)?;
+        config.merge(self.clone())?;
+        config.try_into()
+    }
+
+    /// Merge into `config` over the sources merged so far, like `config.merge(clap)`.
+    ///
+    /// ```
+    /// # #[cfg(feature = "clap2")]
+    /// # fn main() -> Result<(), config::ConfigError> {
+    /// use clap::{App, Arg};
+    /// use clap_config::Clap;
+    ///
+    /// let app = App::new("app").arg(Arg::with_name("format").long("format").takes_value(true));
+    ///
+    /// let mut config = config::Config::new();
+    /// config.merge(config::File::from_str("format = \"yaml\"", config::FileFormat::Toml))?;
+    /// Clap::from_args(app, vec!["myprog", "--format", "json"]).merge_into(&mut config)?;
+    /// assert_eq!(config.get_str("format")?, "json");
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(feature = "clap2"))]
+    /// # fn main() {}
+    /// ```
+    pub fn merge_into(mut self, config: &mut config::Config) -> Result<(), ConfigError> {
+        let values = self.to_value_map()?;
+        let base: HashMap<String, Vec<Value>> = self
+            .arg_keys()
+            .into_iter()
+            .filter(|(name, _, _)| self.append_multiples.contains(*name))
+            .filter_map(|(_, key, _)| {
+                let path = self.config_path(&values, &key)?;
+                Some((key, config.get::<Vec<Value>>(&path).ok()?))
+            })
+            .collect();
+        self.append_base = base;
+        // Values memoized or precomputed before miss the base.
+        self.cache.reset();
+        config.merge(self).map(|_| ())
+    }
+
+    fn from_matches(
+        args: HashMap<String, CliType>,
+        info: HashMap<String, ArgInfo>,
+        app_name: Option<AppName>,
+        matches: ArgMatches,
+    ) -> Self {
+        Self {
+            args,
+            matches,
+            subcommand_field: None,
+            bin_name_field: None,
+            subcommand_fields: Vec::new(),
+            subcommand_field_for: HashMap::new(),
+            append_multiples: HashSet::new(),
+            append_base: HashMap::new(),
+            origin: None,
+            coerce_numbers: false,
+            key_case: KeyCase::None,
+            key_map: None,
+            renames: HashMap::new(),
+            case_insensitive_lookup: false,
+            nested_keys: false,
+            ignore_defaults: false,
+            key_prefix: None,
+            exclude: HashSet::new(),
+            include_only: None,
+            os_values: false,
+            count_as_bool: HashSet::new(),
+            count_levels: HashMap::new(),
+            flatten_subcommands: false,
+            key_from_long: false,
+            emit_aliases: false,
+            alias_keys: HashMap::new(),
+            path_separator: ".".to_owned(),
+            coerce_bools: false,
+            ignore_env_values: false,
+            dedup_multiple: HashSet::new(),
+            sort_multiple: HashSet::new(),
+            bool_strings: HashMap::new(),
+            negatables: Vec::new(),
+            emit_null: HashSet::new(),
+            emit_all_booleans: false,
+            map_args: HashMap::new(),
+            tuples: HashMap::new(),
+            sentinels: HashMap::new(),
+            paths: HashMap::new(),
+            respect_value_hints: false,
+            canonicalize_choices: false,
+            layer: Layer::All,
+            occurrence_counts: None,
+            default_subcommand: None,
+            post_process: None,
+            cache: Memo::default(),
+            trim_values: false,
+            drop_empty: false,
+            app_name,
+            value_maps: HashMap::new(),
+            skip_hidden: false,
+            strict_keys: false,
+            combined: Vec::new(),
+            help: None,
+            #[cfg(any(feature = "clap3", feature = "clap4"))]
+            app: None,
+            info,
+        }
+    }
+
+    fn extract_matches(
+        &self,
+        matches: &ArgMatches,
+        args: &HashMap<String, CliType>,
+        info: &HashMap<String, ArgInfo>,
+        include: Option<&HashSet<String>>,
+    ) -> HashMap<String, Value> {
+        let (mut map, flattened) = args
+            .iter()
+            .filter(|(name, _)| !self.exclude.contains(*name))
+            .filter(|(name, _)| {
+                !self.skip_hidden || !info.get(*name).is_some_and(|info| info.hidden)
+            })
+            .filter(|(name, tpe)| {
+                include.is_none_or(|include| {
+                    include.contains(*name) || matches!(tpe, CliType::Subcommand(_))
+                })
+            })
+            .filter_map(|(name, tpe)| {
+                let info = info.get(name);
+                let conf_name = self.key(name, info)?;
+                let entry = match tpe {
+                    CliType::Multiple | CliType::Single if self.skip_value(matches, name, info) => {
+                        None
+                    }
+                    CliType::Count | CliType::Boolean | CliType::Group(_)
+                        if self.layer == Layer::Defaults =>
+                    {
+                        None
+                    }
+                    CliType::Multiple => matches.raw_values(name).map(|values| {
+                        let values = self.strings(values);
+                        let values: Vec<&str> = match info.and_then(|info| info.delimiter) {
+                            Some(delimiter) => values
+                                .iter()
+                                .flat_map(|value| value.split(delimiter))
+                                .collect(),
+                            None => values.iter().map(AsRef::as_ref).collect(),
+                        };
+                        let values = values
+                            .into_iter()
+                            .filter_map(|value| self.clean(name, value))
+                            .map(|value| self.choice(value, info));
+                        let mut values: Vec<&str> = if self.dedup_multiple.contains(name) {
+                            let mut seen = HashSet::new();
+                            values.filter(|value| seen.insert(*value)).collect()
+                        } else {
+                            values.collect()
+                        };
+                        if self.sort_multiple.contains(name) {
+                            self.sort(&mut values);
+                        }
+                        if let Some(&n) = self.tuples.get(name) {
+                            let tuples: Vec<Value> = values
+                                .chunks(n)
+                                .map(|chunk| self.values(chunk.to_vec()))
+                                .collect();
+                            return (conf_name, Value::new(None, tuples));
+                        }
+                        if let Some(&separator) = self.map_args.get(name) {
+                            return (conf_name, self.table(name, values, separator));
+                        }
+                        match self.value_maps.get(name) {
+                            Some(map) => {
+                                let values: Vec<Value> = values.into_iter().map(&**map).collect();
+                                (conf_name, Value::new(None, values))
+                            }
+                            None => (conf_name, self.values(values)),
+                        }
+                    }),
+                    CliType::Single => {
+                        let value = matches
+                            .raw_values(name)
+                            .and_then(|values| self.strings(values).into_iter().next())
+                            .and_then(|value| {
+                                let value = self.choice(self.clean(name, &value)?, info);
+                                let hinted = self.respect_value_hints
+                                    && info.is_some_and(|info| info.path_hint);
+                                let opts = self.paths.get(name).copied().or_else(|| {
+                                    hinted.then_some(PathOpts {
+                                        expand_tilde: true,
+                                        canonicalize: false,
+                                    })
+                                });
+                                if let Some(opts) = opts {
+                                    return Some(Value::new(None, opts.apply(value)));
+                                }
+                                match self.value_maps.get(name) {
+                                    Some(map) => Some(map(value)),
+                                    None => Some(self.single_value(value)),
+                                }
+                            });
+                        match value {
+                            Some(value) => Some((conf_name, value)),
+                            None if self.emit_null.contains(name) => {
+                                Some((conf_name, Value::new(None, None::<String>)))
+                            }
+                            None => None,
+                        }
+                    }
+                    CliType::Count => match matches.count(name) {
+                        0 => None,
+                        count if self.count_levels.contains_key(name) => {
+                            let levels = &self.count_levels[name];
+                            levels
+                                .get(count as usize)
+                                .or_else(|| levels.last())
+                                .map(|level| (conf_name, Value::new(None, level.as_str())))
+                        }
+                        _ if self.count_as_bool.contains(name) => {
+                            Some((conf_name, Value::new(None, true)))
+                        }
+                        count => Some((conf_name, Value::new(None, count as i64))),
+                    },
+                    CliType::Boolean => match self.bool_strings.get(name) {
+                        Some((present, absent)) => match matches.flag(name) {
+                            Some(true) => Some(present.clone()),
+                            _ => absent.clone(),
+                        }
+                        .map(|value| (conf_name, Value::new(None, value))),
+                        None => matches
+                            .flag(name)
+                            .or_else(|| {
+                                (self.emit_all_booleans && self.layer == Layer::All)
+                                    .then(|| matches.flag_default(name))
+                            })
+                            .map(|value| (conf_name, Value::new(None, value))),
+                    },
+                    CliType::Group(members) => members
+                        .iter()
+                        .find(|member| matches.occurrences(member) > 0)
+                        .map(|member| (conf_name, Value::new(None, member.as_str()))),
+                    CliType::Subcommand(subargs) => {
+                        let include = include.filter(|include| !include.contains(name));
+                        matches
+                            .subcommand_matches(name)
+                            .map(|submatches| {
+                                self.extract_matches(
+                                    submatches,
+                                    subargs,
+                                    info.map_or(&HashMap::new(), |info| &info.args),
+                                    include,
+                                )
+                            })
+                            .filter(|submatches| {
+                                include.is_none() && self.layer != Layer::Defaults
+                                    || !submatches.is_empty()
+                            })
+                            .map(|submatches| (conf_name, Value::new(None, submatches)))
+                    }
+                };
+                entry.map(|(key, value)| (name, tpe, info, key, value))
+            })
+            .fold(
+                (HashMap::new(), Vec::new()),
+                |(mut map, mut flattened), (name, tpe, info, key, value)| {
+                    match tpe {
+                        CliType::Subcommand(_) if self.flatten_subcommands => flattened.push(value),
+                        _ => {
+                            if let (Some(suffix), CliType::Multiple | CliType::Single) =
+                                (&self.occurrence_counts, tpe)
+                            {
+                                let count = matches.occurrences(name) as i64;
+                                let key = format!("{}{}", key, suffix);
+                                self.insert(&mut map, &key, Value::new(None, count));
+                            }
+                            if let Some(info) = info.filter(|_| self.emit_aliases) {
+                                for alias in &info.aliases {
+                                    let alias = self.key_case.apply(alias);
+                                    self.insert(&mut map, &alias, value.clone());
+                                }
+                            }
+                            for extra in self.alias_keys.get(name).into_iter().flatten() {
+                                self.insert(&mut map, extra, value.clone());
+                            }
+                            self.insert(&mut map, &key, value);
+                        }
+                    }
+                    (map, flattened)
+                },
+            );
+
+        for (key, value) in flattened
+            .into_iter()
+            .filter_map(|value| value.into_table().ok())
+            .flatten()
+        {
+            if map.contains_key(&key) {
+                log::warn!("subcommand arg `{}` overrides the parent arg", key);
+            }
+            map.insert(key, value);
+        }
+        map
+    }
+
+    fn check_keys(
+        &self,
+        matches: &ArgMatches,
+        args: &HashMap<String, CliType>,
+        info: &HashMap<String, ArgInfo>,
+        include: Option<&HashSet<String>>,
+    ) -> Result<(), ConfigError> {
+        // Only the args `extract_matches` collects, see `include_only`.
+        let mut names: Vec<_> = args
+            .iter()
+            .filter(|(name, _)| !self.exclude.contains(*name))
+            .filter(|(name, tpe)| {
+                include.is_none_or(|include| {
+                    include.contains(*name) || matches!(tpe, CliType::Subcommand(_))
+                })
+            })
+            .collect();
+        names.sort_by_key(|(name, _)| *name);
+
+        let mut keys: HashMap<String, (&String, HashMap<String, Value>)> = HashMap::new();
+        for (name, tpe) in names {
+            let info = info.get(name);
+            if let (CliType::Subcommand(args), Some(matches)) =
+                (tpe, matches.subcommand_matches(name))
+            {
+                let empty = HashMap::new();
+                let subinfo = info.map_or(&empty, |info| &info.args);
+                let include = include.filter(|include| !include.contains(name));
+                self.check_keys(matches, args, subinfo, include)?;
+            }
+            let key = match self.key(name, info) {
+                Some(key) => key,
+                None => continue,
+            };
+            // Collect the arg on its own to compare what each arg contributes.
+            let mut arg = HashMap::new();
+            arg.insert(name.clone(), tpe.clone());
+            let arg_info: HashMap<String, ArgInfo> = info
+                .map(|info| (name.clone(), info.clone()))
+                .into_iter()
+                .collect();
+            let values = self.extract_matches(matches, &arg, &arg_info, include);
+            if values.is_empty() {
+                continue;
+            }
+            match keys.get(&key) {
+                Some((other, other_values)) if *other_values != values => {
+                    return Err(ConfigError::Message(format!(
+                        "args `{}` and `{}` are both collected as `{}`",
+                        other, name, key
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    keys.insert(key, (name, values));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_value(&self, matches: &ArgMatches, name: &str, info: Option<&ArgInfo>) -> bool {
+        let given = matches.occurrences(name) > 0;
+        if !given && negated_by_subcommand(matches, info) {
+            return true;
+        }
+        let has_default = matches
+            .is_default(name)
+            .unwrap_or_else(|| info.is_some_and(|info| info.has_default));
+        let env = matches
+            .is_env(name)
+            .unwrap_or_else(|| info.is_some_and(|info| info.env));
+        match self.layer {
+            Layer::All => {
+                !given && (self.ignore_defaults && has_default || self.ignore_env_values && env)
+            }
+            Layer::User => !given,
+            Layer::Defaults => given || !has_default,
+            // The matches only know whether the default was used, not whether a given
+            // value equals it, so compare with the declared defaults.
+            Layer::Changed => {
+                !given
+                    || info.is_some_and(|info| {
+                        let values = matches.raw_values(name).unwrap_or_default();
+                        info.has_default
+                            && values
+                                .into_iter()
+                                .eq(info.default_values.iter().map(OsString::as_os_str))
+                    })
+            }
+        }
+    }
+
+    fn subcommand_path(&self) -> Option<String> {
+        let mut names = Vec::new();
+        let mut matches = &self.matches;
+        while let Some(name) = matches.subcommand_name() {
+            names.push(self.key_case.apply(name));
+            matches = matches.subcommand_matches(name)?;
+        }
+        (!names.is_empty()).then(|| names.join(&self.path_separator))
+    }
+
+    /// Names and info of all non-subcommand args with the keys they are collected under.
+    fn arg_keys(&self) -> Vec<(&str, String, Option<&ArgInfo>)> {
+        fn walk<'a>(
+            clap: &Clap,
+            prefix: &str,
+            args: &'a HashMap<String, CliType>,
+            info: Option<&'a HashMap<String, ArgInfo>>,
+            keys: &mut Vec<(&'a str, String, Option<&'a ArgInfo>)>,
+        ) {
+            for (name, tpe) in args
+                .iter()
+                .filter(|(name, _)| !clap.exclude.contains(*name))
+            {
+                let info = info.and_then(|info| info.get(name));
+                let key = match clap.key(name, info) {
+                    Some(key) if prefix.is_empty() => key,
+                    Some(key) => format!("{}{}{}", prefix, clap.path_separator, key),
+                    None => continue,
+                };
+                match tpe {
+                    CliType::Subcommand(subargs) => {
+                        let prefix = if clap.flatten_subcommands {
+                            prefix
+                        } else {
+                            &key
+                        };
+                        walk(clap, prefix, subargs, info.map(|info| &info.args), keys);
+                    }
+                    _ => keys.push((name, key, info)),
+                }
+            }
+        }
+
+        let mut keys = Vec::new();
+        let prefix = self.key_prefix.as_deref().unwrap_or_default();
+        walk(self, prefix, &self.args, Some(&self.info), &mut keys);
+        keys
+    }
+
+    /// Name of the arg collected under `key` with the matches of its subcommand,
+    /// if it is a top level arg or one of the chosen subcommand.
+    #[cfg(any(feature = "clap3", feature = "clap4"))]
+    fn arg_for_key(&self, key: &str) -> Option<(&str, &ArgMatches)> {
+        fn walk<'a>(
+            clap: &Clap,
+            prefix: &str,
+            key: &str,
+            matches: &'a ArgMatches,
+            args: &'a HashMap<String, CliType>,
+            info: Option<&HashMap<String, ArgInfo>>,
+        ) -> Option<(&'a str, &'a ArgMatches)> {
+            for (name, tpe) in args
+                .iter()
+                .filter(|(name, _)| !clap.exclude.contains(*name))
+            {
+                let info = info.and_then(|info| info.get(name));
+                let arg_key = match clap.key(name, info) {
+                    Some(arg_key) if prefix.is_empty() => arg_key,
+                    Some(arg_key) => format!("{}{}{}", prefix, clap.path_separator, arg_key),
+                    None => continue,
+                };
+                match tpe {
+                    CliType::Subcommand(subargs) => {
+                        let submatches = match matches.subcommand_matches(name) {
+                            Some(submatches) => submatches,
+                            None => continue,
+                        };
+                        let prefix = if clap.flatten_subcommands {
+                            prefix
+                        } else {
+                            &arg_key
+                        };
+                        let subinfo = info.map(|info| &info.args);
+                        if let Some(found) = walk(clap, prefix, key, submatches, subargs, subinfo) {
+                            return Some(found);
+                        }
+                    }
+                    _ if arg_key == key => return Some((name, matches)),
+                    _ => {}
+                }
+            }
+            None
+        }
+
+        let prefix = self.key_prefix.as_deref().unwrap_or_default();
+        walk(
+            self,
+            prefix,
+            key,
+            &self.matches,
+            &self.args,
+            Some(&self.info),
+        )
+    }
+
+    /// Report required args missing from the matches by their config keys,
+    /// falling back to the clap `error` when there are none.
+    fn missing_required_error<E>(&self, error: E) -> ConfigError
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        fn walk(
+            clap: &Clap,
+            prefix: &str,
+            matches: &ArgMatches,
+            args: &HashMap<String, CliType>,
+            info: &HashMap<String, ArgInfo>,
+            missing: &mut Vec<String>,
+        ) {
+            for (name, tpe) in args {
+                let info = info.get(name);
+                let key = clap.key(name, info).unwrap_or_else(|| name.clone());
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}{}{}", prefix, clap.path_separator, key)
+                };
+                match tpe {
+                    CliType::Subcommand(subargs) => {
+                        if let Some(submatches) = matches.subcommand_matches(name) {
+                            let prefix = if clap.flatten_subcommands {
+                                prefix
+                            } else {
+                                &key
+                            };
+                            let empty = HashMap::new();
+                            let subinfo = info.map_or(&empty, |info| &info.args);
+                            walk(clap, prefix, submatches, subargs, subinfo, missing);
+                        }
+                    }
+                    _ if info.is_some_and(|info| info.required)
+                        && !negated_by_subcommand(matches, info)
+                        && matches.raw_values(name).is_none()
+                        && matches.occurrences(name) == 0 =>
+                    {
+                        missing.push(key)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut missing = Vec::new();
+        let prefix = self.key_prefix.as_deref().unwrap_or_default();
+        walk(
+            self,
+            prefix,
+            &self.matches,
+            &self.args,
+            &self.info,
+            &mut missing,
+        );
+        if missing.is_empty() {
+            return ConfigError::Foreign(Box::new(error));
+        }
+        missing.sort();
+        ConfigError::Message(format!(
+            "missing required config keys: {}",
+            missing.join(", ")
+        ))
+    }
+
+    fn insert_subcommand_fields(
+        &self,
+        map: &mut HashMap<String, Value>,
+        matches: &ArgMatches,
+        info: &HashMap<String, ArgInfo>,
+        parent: Option<&str>,
+        depth: usize,
+    ) {
+        let name = match matches.subcommand_name() {
+            Some(name) => name,
+            None => return,
+        };
+        let field = parent
+            .and_then(|parent| self.subcommand_field_for.get(parent))
+            .or_else(|| {
+                let last = self.subcommand_fields.len().checked_sub(1)?;
+                self.subcommand_fields.get(depth.min(last))
+            });
+        if let Some(field) = field {
+            self.insert(map, field, Value::new(None, self.key_case.apply(name)));
+        }
+
+        let info = info.get(name);
+        let nested = matches
+            .subcommand_matches(name)
+            .filter(|matches| matches.subcommand_name().is_some());
+        if let (Some(matches), Some(key)) = (nested, self.key(name, info)) {
+            let mut table = map
+                .remove(&key)
+                .and_then(|table| table.into_table().ok())
+                .unwrap_or_default();
+            self.insert_subcommand_fields(
+                &mut table,
+                matches,
+                info.map_or(&HashMap::new(), |info| &info.args),
+                Some(name),
+                depth + 1,
+            );
+            map.insert(key, Value::new(None, table));
+        }
+    }
+
+    /// Prepend `base` to the array at the `key` path, looking into nested tables.
+    fn append(&self, map: &mut HashMap<String, Value>, key: &str, base: &[Value]) {
+        if let Some(value) = map.remove(key) {
+            let value = match value.clone().into_array() {
+                Ok(values) => {
+                    Value::new(None, base.iter().cloned().chain(values).collect::<Vec<_>>())
+                }
+                Err(_) => value,
+            };
+            map.insert(key.to_owned(), value);
+            return;
+        }
+        for (index, _) in key.match_indices(self.path_separator.as_str()) {
+            let (head, rest) = (&key[..index], &key[index + self.path_separator.len()..]);
+            if let Some(Ok(mut table)) = map.get(head).map(|table| table.clone().into_table()) {
+                self.append(&mut table, rest, base);
+                map.insert(head.to_owned(), Value::new(None, table));
+                return;
+            }
+        }
+    }
+
+    /// Path of the collected `key` for `config.get`: the tables `append` finds it in,
+    /// joined with `.` instead of the `path_separator`.
+    fn config_path(&self, map: &HashMap<String, Value>, key: &str) -> Option<String> {
+        if map.contains_key(key) {
+            return Some(key.to_owned());
+        }
+        for (index, _) in key.match_indices(self.path_separator.as_str()) {
+            let (head, rest) = (&key[..index], &key[index + self.path_separator.len()..]);
+            if let Some(Ok(table)) = map.get(head).map(|table| table.clone().into_table()) {
+                return Some(format!("{}.{}", head, self.config_path(&table, rest)?));
+            }
+        }
+        None
+    }
+
+    fn insert(&self, map: &mut HashMap<String, Value>, key: &str, value: Value) {
+        match key
+            .split_once(self.path_separator.as_str())
+            .filter(|_| self.nested_keys)
+        {
+            Some((head, rest)) => {
+                let mut table = map
+                    .remove(head)
+                    .and_then(|table| table.into_table().ok())
+                    .unwrap_or_default();
+                self.insert(&mut table, rest, value);
+                map.insert(head.to_owned(), Value::new(None, table));
+            }
+            None => {
+                map.insert(key.to_owned(), value);
+            }
+        }
+    }
+
+    /// Entry of `map` for the arg `name`, see `case_insensitive_lookup`.
+    fn lookup<'a, T>(
+        &self,
+        map: &'a HashMap<String, T>,
+        name: &str,
+    ) -> Option<(&'a String, &'a T)> {
+        map.get_key_value(name).or_else(|| {
+            map.iter()
+                .filter(|_| self.case_insensitive_lookup)
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        })
+    }
+
+    fn key(&self, name: &str, info: Option<&ArgInfo>) -> Option<String> {
+        if let Some((_, key)) = self.lookup(&self.renames, name) {
+            return Some(key.clone());
+        }
+        let name = match info.and_then(|info| info.long.as_deref()) {
+            Some(long) if self.key_from_long => long,
+            _ => name,
+        };
+        let key = match &self.key_map {
+            Some(map_key) => map_key(name)?,
+            None => name.to_owned(),
+        };
+        Some(self.key_case.apply(&key))
+    }
+
+    fn strings<'a>(&self, values: Vec<&'a OsStr>) -> Vec<Cow<'a, str>> {
+        if self.os_values {
+            values.into_iter().map(OsStr::to_string_lossy).collect()
+        } else {
+            values
+                .into_iter()
+                .filter_map(OsStr::to_str)
+                .map(Cow::Borrowed)
+                .collect()
+        }
+    }
+
+    fn value(&self, value: &str) -> Value {
+        self.coerce_numbers
+            .then(|| number(value))
+            .flatten()
+            .unwrap_or_else(|| Value::new(None, value))
+    }
+
+    fn clean<'a>(&self, name: &str, value: &'a str) -> Option<&'a str> {
+        let value = if self.trim_values {
+            value.trim()
+        } else {
+            value
+        };
+        Some(value)
+            .filter(|value| !(self.drop_empty && value.is_empty()))
+            .filter(|value| {
+                self.sentinels
+                    .get(name)
+                    .is_none_or(|sentinel| sentinel != value)
+            })
+    }
+
+    fn choice<'a>(&self, value: &'a str, info: Option<&'a ArgInfo>) -> &'a str {
+        if !self.canonicalize_choices {
+            return value;
+        }
+        info.into_iter()
+            .flat_map(|info| &info.possible_values)
+            .find(|choice| choice.eq_ignore_ascii_case(value))
+            .map_or(value, String::as_str)
+    }
+
+    fn single_value(&self, value: &str) -> Value {
+        // Same order as `values`: numbers, then booleans.
+        if let Some(number) = self.coerce_numbers.then(|| number(value)).flatten() {
+            return number;
+        }
+        match self.coerce_bools.then(|| boolean(value)).flatten() {
+            Some(boolean) => Value::new(None, boolean),
+            None => Value::new(None, value),
+        }
+    }
+
+    fn table(&self, name: &str, values: Vec<&str>, separator: char) -> Value {
+        let table: HashMap<String, Value> = values
+            .into_iter()
+            .filter_map(|value| match value.split_once(separator) {
+                Some((key, value)) => Some((key.to_owned(), self.value(value))),
+                None => {
+                    log::warn!(
+                        "skipping `{}` value `{}` without `{}`",
+                        name,
+                        value,
+                        separator
+                    );
+                    None
+                }
+            })
+            .collect();
+        Value::new(None, table)
+    }
+
+    fn sort(&self, values: &mut Vec<&str>) {
+        let numbers: Option<Vec<f64>> = self
+            .coerce_numbers
+            .then(|| values.iter().map(|value| value.parse().ok()).collect())
+            .flatten();
+        match numbers {
+            Some(numbers) => {
+                let mut pairs: Vec<_> = numbers.into_iter().zip(values.drain(..)).collect();
+                pairs.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                values.extend(pairs.into_iter().map(|(_, value)| value));
+            }
+            None => values.sort_unstable(),
+        }
+    }
+
+    /// Coerces all of the values to the same type, trying integers, floats
+    /// and booleans in turn, or keeps them all as strings.
+    fn values(&self, values: Vec<&str>) -> Value {
+        if self.coerce_numbers {
+            let ints: Result<Vec<i64>, _> = values.iter().map(|value| value.parse()).collect();
+            if let Ok(ints) = ints {
+                return Value::new(None, ints);
+            }
+            let floats: Option<Vec<f64>> = values.iter().map(|value| float(value)).collect();
+            if let Some(floats) = floats {
+                return Value::new(None, floats);
+            }
+        }
+        if self.coerce_bools {
+            let bools: Option<Vec<bool>> = values.iter().map(|value| boolean(value)).collect();
+            if let Some(bools) = bools {
+                return Value::new(None, bools);
+            }
+        }
+        Value::new(None, values)
+    }
+}
+
+fn with_origin(value: Value, origin: &String) -> Value {
+    if let Ok(table) = value.clone().into_table() {
+        let table: HashMap<String, Value> = table
+            .into_iter()
+            .map(|(key, value)| (key, with_origin(value, origin)))
+            .collect();
+        Value::new(Some(origin), table)
+    } else if let Ok(array) = value.clone().into_array() {
+        let array: Vec<Value> = array
+            .into_iter()
+            .map(|value| with_origin(value, origin))
+            .collect();
+        Value::new(Some(origin), array)
+    } else {
+        Value::new(Some(origin), value.kind)
+    }
+}
+
+/// Whether the arg is required, but not now since a subcommand was chosen and
+/// the app has `SubcommandsNegateReqs`. Its default, if any, is stale then.
+fn negated_by_subcommand(matches: &ArgMatches, info: Option<&ArgInfo>) -> bool {
+    info.is_some_and(|info| info.required && info.subcommands_negate_reqs)
+        && matches.subcommand_name().is_some()
+}
+
+/// Values of an option grouped by the occurrence they were given in: values of
+/// one occurrence have consecutive `indices`, the option itself sits between two.
+fn occurrence_values(indices: Vec<usize>, values: Vec<String>) -> Vec<Vec<String>> {
+    if indices.len() != values.len() {
+        return values.into_iter().map(|value| vec![value]).collect();
+    }
+    let mut occurrences: Vec<Vec<String>> = Vec::new();
+    let mut previous = None;
+    for (index, value) in indices.into_iter().zip(values) {
+        match occurrences.last_mut() {
+            Some(occurrence) if previous.map(|previous| previous + 1) == Some(index) => {
+                occurrence.push(value)
+            }
+            _ => occurrences.push(vec![value]),
+        }
+        previous = Some(index);
+    }
+    occurrences
+}
+
+/// File name of the binary path `arg`, the way clap names the binary.
+fn file_name(arg: &OsStr) -> Option<String> {
+    Path::new(arg).file_name()?.to_str().map(str::to_owned)
+}
+
+fn boolean(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn number(value: &str) -> Option<Value> {
+    if let Ok(int) = value.parse::<i64>() {
+        return Some(Value::new(None, int));
+    }
+    float(value).map(|float| Value::new(None, float))
+}
+
+/// Parse a finite float, except integers that overflow `i64`: config has no
+/// `u64` values, so those are kept as strings instead of losing precision.
+fn float(value: &str) -> Option<f64> {
+    if value
+        .parse::<i128>()
+        .is_ok_and(|int| int > i128::from(i64::MAX) || int < i128::from(i64::MIN))
+    {
+        return None;
+    }
+    value.parse().ok().filter(|float: &f64| float.is_finite())
+}
+
+impl Source for Clap {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new((*self).clone())
+    }
+
+    fn collect(&self) -> Result<HashMap<String, Value>, ConfigError> {
+        let cache = match &*self.cache {
+            Some(cache) => cache,
+            None => return self.to_value_map(),
+        };
+        let mut cache = cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(values) = &*cache {
+            return Ok(values.clone());
+        }
+        let values = self.to_value_map()?;
+        *cache = Some(values.clone());
+        Ok(values)
+    }
+}
+
+#[cfg(all(test, feature = "clap2"))]
+mod tests {
+    use super::*;
+    use clap::{App, Arg, ArgGroup};
+    use serde_derive::Deserialize;
+    use std::ffi::OsString;
+
+    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+    #[serde(default)]
+    pub struct Config {
+        pub format: Option<String>,
+        pub verbosity: usize,
+        pub subcommand: Option<SubConfig>,
+        pub mode: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+    #[serde(default)]
+    pub struct SubConfig {
+        pub ids: Vec<u32>,
+        pub flag: bool,
+    }
+
+    fn new_app() -> App<'static, 'static> {
+        App::new("app")
+            .arg(
+                Arg::with_name("format")
+                    .takes_value(true)
+                    .short("f")
+                    .long("format"),
+            )
+            .arg(
+                Arg::with_name("verbosity")
+                    .short("v")
+                    .long("verbose")
+                    .multiple(true),
+            )
+            .subcommand(
+                App::new("subcommand")
+                    .arg(Arg::with_name("flag").short("F").long("flag"))
+                    .arg(
+                        Arg::with_name("ids")
+                            .short("i")
+                            .long("id")
+                            .required(true)
+                            .takes_value(true)
+                            .multiple(true),
+                    ),
+            )
+    }
+
+    fn new_clap_config<I>(args: I) -> Clap
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        Clap::from_args(new_app(), args).subcommand_field("mode")
+    }
+
+    fn test_clap_with_args(args: Vec<&str>, expected: Config) {
+        let mut conf = config::Config::new();
+        let clap = new_clap_config(args);
+
+        log::debug!("CLAP: {:?}", clap);
+
+        conf.merge(clap).unwrap();
+        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    }
+
+    fn test_clap_over_file(file: &str, args: Vec<&str>, expected: Config) {
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(file, config::FileFormat::Toml))
+            .unwrap()
+            .merge(new_clap_config(args))
+            .unwrap();
+        assert_eq!(conf.try_into::<Config>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_clap() {
+        env_logger::init();
+
+        test_clap_with_args(
+            vec![
+                "myprog",
+                "-vvv",
+                "--format=json",
+                "subcommand",
+                "-i1",
+                "-i2",
+                "-i3",
+            ],
+            Config {
+                format: Some("json".into()),
+                verbosity: 3,
+                subcommand: Some(SubConfig {
+                    ids: vec![1, 2, 3],
+                    flag: false,
+                }),
+                mode: Some("subcommand".into()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_absent_boolean_keeps_file_value() {
+        test_clap_over_file(
+            "[subcommand]\nflag = true\n",
+            vec!["myprog", "subcommand", "-i1"],
+            Config {
+                subcommand: Some(SubConfig {
+                    ids: vec![1],
+                    flag: true,
+                }),
+                mode: Some("subcommand".into()),
+                ..Config::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_zero_count_keeps_file_value() {
+        test_clap_over_file(
+            "verbosity = 5\n",
+            vec!["myprog"],
+            Config {
+                verbosity: 5,
+                ..Config::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_coerce_numbers() {
+        let app = App::new("app")
+            .arg(Arg::with_name("port").long("port").takes_value(true))
+            .arg(Arg::with_name("ratio").long("ratio").takes_value(true))
+            .arg(Arg::with_name("name").long("name").takes_value(true));
+        let clap = Clap::from_args(
+            app,
+            vec![
+                "myprog", "--port", "8080", "--ratio", "1.5", "--name", "foo",
+            ],
+        )
+        .coerce_numbers(true);
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["port"], Value::new(None, 8080));
+        assert_eq!(values["ratio"], Value::new(None, 1.5));
+        assert_eq!(values["name"], Value::new(None, "foo"));
+    }
+
+    #[test]
+    fn test_value_delimiter() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Ids {
+            ids: Vec<u32>,
+        }
+
+        let app = App::new("app").arg(Arg::with_name("ids").long("ids").value_delimiter(","));
+        let mut conf = config::Config::new();
+        conf.merge(Clap::from_args(app, vec!["myprog", "--ids=1,2,3"]))
+            .unwrap();
+        assert_eq!(conf.try_into::<Ids>().unwrap(), Ids { ids: vec![1, 2, 3] });
+    }
+
+    #[test]
+    fn test_rename_keys() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Logging {
+            log_level: String,
+        }
+
+        let app = App::new("app").arg(
+            Arg::with_name("log-level")
+                .long("log-level")
+                .takes_value(true),
+        );
+        let mut conf = config::Config::new();
+        conf.merge(
+            Clap::from_args(app, vec!["myprog", "--log-level", "debug"])
+                .rename_keys(KeyCase::SnakeCase),
+        )
+        .unwrap();
+        assert_eq!(
+            conf.try_into::<Logging>().unwrap(),
+            Logging {
+                log_level: "debug".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_map_key() {
+        let app = App::new("app")
+            .arg(Arg::with_name("db").long("db").takes_value(true))
+            .arg(Arg::with_name("config").long("config").takes_value(true));
+        let clap = Clap::from_args(
+            app,
+            vec!["myprog", "--db", "postgres://db", "--config", "app.toml"],
+        )
+        .map_key(|name| match name {
+            "db" => Some("database.url".into()),
+            "config" => None,
+            name => Some(name.into()),
+        });
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values.len(), 1);
+
+        let mut conf = config::Config::new();
+        conf.merge(clap).unwrap();
+        assert_eq!(conf.get_str("database.url").unwrap(), "postgres://db");
+        assert!(conf.get_str("config").is_err());
+    }
+
+    #[test]
+    fn test_nested_keys() {
+        let app = App::new("app")
+            .arg(Arg::with_name("db.host").long("db-host").takes_value(true))
+            .arg(Arg::with_name("db.port").long("db-port").takes_value(true));
+        let clap = Clap::from_args(
+            app,
+            vec!["myprog", "--db-host", "localhost", "--db-port", "5432"],
+        )
+        .nested_keys(true);
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values.len(), 1);
+        let db = values["db"].clone().into_table().unwrap();
+        assert_eq!(db["host"], Value::new(None, "localhost"));
+        assert_eq!(db["port"], Value::new(None, "5432"));
+    }
+
+    #[test]
+    fn test_ignore_defaults() {
+        let app = App::new("app").arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .default_value("text"),
+        );
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(
+            "format = \"json\"",
+            config::FileFormat::Toml,
+        ))
+        .unwrap()
+        .merge(Clap::from_args(app, vec!["myprog"]).ignore_defaults(true))
+        .unwrap();
+        assert_eq!(conf.get_str("format").unwrap(), "json");
+    }
+
+    #[test]
+    fn test_key_prefix() {
+        let clap = new_clap_config(vec!["myprog", "--format", "json", "subcommand", "-i1"])
+            .key_prefix("cli");
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["cli.format"], Value::new(None, "json"));
+        assert_eq!(values["cli.mode"], Value::new(None, "subcommand"));
+        assert!(values.contains_key("cli.subcommand"));
+        assert!(!values.contains_key("format"));
+    }
+
+    #[test]
+    fn test_to_value_map() {
+        let values = new_clap_config(vec!["myprog", "-vv", "--format=yaml"])
+            .to_value_map()
+            .unwrap();
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["format"], Value::new(None, "yaml"));
+        assert_eq!(values["verbosity"], Value::new(None, 2));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let app = new_app().arg(Arg::with_name("config").long("config").takes_value(true));
+        let values = Clap::from_args(app, vec!["myprog", "--config", "foo.toml", "-f", "json"])
+            .exclude(&["config"])
+            .collect()
+            .unwrap();
+
+        assert!(!values.contains_key("config"));
+        assert_eq!(values["format"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_include_only() {
+        let values = new_clap_config(vec!["myprog", "-vv", "--format=json", "subcommand", "-i1"])
+            .include_only(&["format"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(values["mode"], Value::new(None, "subcommand"));
+    }
+
+    #[test]
+    fn test_include_only_subcommand() {
+        let values = new_clap_config(vec!["myprog", "-vv", "subcommand", "-F", "-i1"])
+            .include_only(&["subcommand"])
+            .collect()
+            .unwrap();
+
+        assert!(!values.contains_key("verbosity"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert_eq!(subcommand.len(), 2);
+        assert_eq!(subcommand["flag"], Value::new(None, true));
+    }
+
+    #[test]
+    fn test_try_from_args_missing_required() {
+        let error = Clap::try_from_args(new_app(), vec!["myprog", "subcommand"]).unwrap_err();
+        assert_eq!(error.kind, clap::ErrorKind::MissingRequiredArgument);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_os_values() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let path = OsString::from_vec(b"/tmp/\xffpath".to_vec());
+        let clap = new_clap_config(vec![OsString::from("myprog"), OsString::from("-f"), path]);
+        assert!(!clap.collect().unwrap().contains_key("format"));
+
+        let values = clap.os_values(true).collect().unwrap();
+        assert_eq!(values["format"], Value::new(None, "/tmp/\u{fffd}path"));
+    }
+
+    #[test]
+    fn test_count_as_bool() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Flags {
+            debug: bool,
+        }
+
+        let app = App::new("app").arg(Arg::with_name("debug").short("d").multiple(true));
+        let mut conf = config::Config::new();
+        conf.merge(Clap::from_args(app, vec!["myprog", "-dd"]).count_as_bool(&["debug"]))
+            .unwrap();
+        assert_eq!(conf.try_into::<Flags>().unwrap(), Flags { debug: true });
+    }
+
+    #[test]
+    fn test_nested_subcommand_field() {
+        let app =
+            App::new("app")
+                .subcommand(App::new("db").subcommand(
+                    App::new("migrate").arg(Arg::with_name("dry-run").long("dry-run")),
+                ));
+        let values = Clap::from_args(app, vec!["myprog", "db", "migrate", "--dry-run"])
+            .subcommand_field("mode")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["mode"], Value::new(None, "db.migrate"));
+    }
+
+    #[test]
+    fn test_flatten_subcommands() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Flat {
+            ids: Vec<u32>,
+            flag: bool,
+            mode: String,
+        }
+
+        let mut conf = config::Config::new();
+        conf.merge(
+            new_clap_config(vec!["myprog", "subcommand", "-F", "-i1", "-i2"])
+                .flatten_subcommands(true),
+        )
+        .unwrap();
+        assert_eq!(
+            conf.try_into::<Flat>().unwrap(),
+            Flat {
+                ids: vec![1, 2],
+                flag: true,
+                mode: "subcommand".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_key_from_long() {
+        let app = App::new("app")
+            .arg(Arg::with_name("v").long("verbose").multiple(true))
+            .arg(Arg::with_name("f").takes_value(true).short("f"));
+        let values = Clap::from_args(app, vec!["myprog", "--verbose", "-f", "json"])
+            .key_from_long(true)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["verbose"], Value::new(None, 1));
+        assert_eq!(values["f"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_coerce_multiple_numbers() {
+        let app = App::new("app").arg(
+            Arg::with_name("id")
+                .long("id")
+                .takes_value(true)
+                .multiple(true),
+        );
+
+        let values = Clap::from_args(app.clone(), vec!["myprog", "--id", "1", "--id", "2"])
+            .coerce_numbers(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["id"], Value::new(None, vec![1i64, 2]));
+
+        let values = Clap::from_args(app, vec!["myprog", "--id", "1", "--id", "two"])
+            .coerce_numbers(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["id"], Value::new(None, vec!["1", "two"]));
+    }
+
+    #[test]
+    fn test_arg_group() {
+        let app = App::new("app")
+            .arg(Arg::with_name("json").long("json"))
+            .arg(Arg::with_name("yaml").long("yaml"))
+            .group(ArgGroup::with_name("format").args(&["json", "yaml"]));
+
+        let values = Clap::from_args(app.clone(), vec!["myprog", "--yaml"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["format"], Value::new(None, "yaml"));
+
+        let values = Clap::from_args(app, vec!["myprog"]).collect().unwrap();
+        assert!(!values.contains_key("format"));
+    }
+
+    #[test]
+    fn test_emit_aliases() {
+        let app = App::new("app").arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .visible_alias("colour"),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "--colour", "never"])
+            .emit_aliases(true)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["color"], Value::new(None, "never"));
+        assert_eq!(values["colour"], Value::new(None, "never"));
+    }
+
+    #[test]
+    fn test_rename_keys_case() {
+        let new_clap = || {
+            let app = App::new("app").arg(
+                Arg::with_name("LogLevel")
+                    .long("log-level")
+                    .takes_value(true),
+            );
+            Clap::from_args(app, vec!["myprog", "--log-level", "debug"])
+        };
+
+        let values = new_clap()
+            .rename_keys(KeyCase::Lowercase)
+            .collect()
+            .unwrap();
+        assert_eq!(values["loglevel"], Value::new(None, "debug"));
+
+        let values = new_clap()
+            .rename_keys(KeyCase::Uppercase)
+            .collect()
+            .unwrap();
+        assert_eq!(values["LOGLEVEL"], Value::new(None, "debug"));
+    }
+
+    #[cfg(feature = "figment")]
+    #[test]
+    fn test_figment() {
+        use ::figment::providers::{Format, Toml};
+        use ::figment::Figment;
+
+        let options: Config = Figment::new()
+            .merge(Toml::string("format = \"yaml\"\nverbosity = 1\n"))
+            .merge(
+                new_clap_config(vec!["myprog", "--format=json", "subcommand", "-i1", "-i2"])
+                    .coerce_numbers(true),
+            )
+            .extract()
+            .unwrap();
+
+        assert_eq!(
+            options,
+            Config {
+                format: Some("json".into()),
+                verbosity: 1,
+                subcommand: Some(SubConfig {
+                    ids: vec![1, 2],
+                    flag: false,
+                }),
+                mode: Some("subcommand".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_path_separator() {
+        let app = App::new("app").subcommand(App::new("db").subcommand(App::new("migrate")));
+        let values = Clap::from_args(app, vec!["myprog", "db", "migrate"])
+            .subcommand_field("mode")
+            .path_separator("__")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["mode"], Value::new(None, "db__migrate"));
+    }
+
+    #[test]
+    fn test_present_keys() {
+        let clap = new_clap_config(vec!["myprog", "-v", "subcommand", "-i1"]);
+        assert_eq!(
+            clap.present_keys(),
+            vec!["mode", "subcommand.ids", "verbosity"]
+        );
+    }
+
+    #[test]
+    fn test_force_type() {
+        let values = new_clap_config(vec!["myprog", "--format", "json"])
+            .force_type("format", CliType::Boolean)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["format"], Value::new(None, true));
+    }
+
+    #[test]
+    fn test_coerce_bools() {
+        let new_clap = |value| {
+            let app =
+                App::new("app").arg(Arg::with_name("feature").long("feature").takes_value(true));
+            Clap::from_args(app, vec!["myprog", "--feature", value]).coerce_bools(true)
+        };
+
+        let values = new_clap("On").collect().unwrap();
+        assert_eq!(values["feature"], Value::new(None, true));
+
+        let values = new_clap("maybe").collect().unwrap();
+        assert_eq!(values["feature"], Value::new(None, "maybe"));
+
+        let values = new_clap("1").collect().unwrap();
+        assert_eq!(values["feature"], Value::new(None, true));
+    }
+
+    #[test]
+    fn test_coerce_bools_and_numbers() {
+        let new_app = || {
+            App::new("app").arg(
+                Arg::with_name("workers")
+                    .long("workers")
+                    .takes_value(true)
+                    .multiple(true),
+            )
+        };
+
+        let values = Clap::from_args(new_app(), vec!["myprog", "--workers", "1"])
+            .coerce_bools(true)
+            .coerce_numbers(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["workers"], Value::new(None, vec![1]));
+
+        let app = App::new("app").arg(Arg::with_name("workers").long("workers").takes_value(true));
+        let values = Clap::from_args(app, vec!["myprog", "--workers", "1"])
+            .coerce_bools(true)
+            .coerce_numbers(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["workers"], Value::new(None, 1));
+    }
+
+    #[test]
+    fn test_ignore_env_values() {
+        std::env::set_var("CLAP_CONFIG_TEST_FORMAT", "yaml");
+        let app = App::new("app").arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .env("CLAP_CONFIG_TEST_FORMAT"),
+        );
+
+        let values = Clap::from_args(app.clone(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["format"], Value::new(None, "yaml"));
+
+        let values = Clap::from_args(app, vec!["myprog"])
+            .ignore_env_values(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("format"));
+    }
+
+    #[test]
+    fn test_dedup_multiple() {
+        let app = App::new("app").arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true),
+        );
+        let args = vec!["myprog", "--tag", "a", "--tag", "b", "--tag", "a"];
+
+        let values = Clap::from_args(app.clone(), args.clone())
+            .collect()
+            .unwrap();
+        assert_eq!(values["tag"], Value::new(None, vec!["a", "b", "a"]));
+
+        let values = Clap::from_args(app, args)
+            .dedup_multiple(&["tag"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["tag"], Value::new(None, vec!["a", "b"]));
+    }
+
+    #[test]
+    fn test_canonicalize_choices() {
+        let app = App::new("app").arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["json", "yaml"])
+                .case_insensitive(true),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "--format", "JSON"])
+            .canonicalize_choices(true)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["format"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_default_and_user_layers() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Layers {
+            format: String,
+            level: String,
+        }
+
+        let new_app = || {
+            App::new("app")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .default_value("text"),
+                )
+                .arg(
+                    Arg::with_name("level")
+                        .long("level")
+                        .takes_value(true)
+                        .default_value("info"),
+                )
+        };
+        let layered = |args: Vec<&str>| {
+            let mut conf = config::Config::new();
+            conf.merge(Clap::from_args(new_app(), args.clone()).defaults_only())
+                .unwrap()
+                .merge(config::File::from_str(
+                    "format = \"yaml\"",
+                    config::FileFormat::Toml,
+                ))
+                .unwrap()
+                .merge(Clap::from_args(new_app(), args).user_only())
+                .unwrap();
+            conf.try_into::<Layers>().unwrap()
+        };
+
+        assert_eq!(
+            layered(vec!["myprog"]),
+            Layers {
+                format: "yaml".into(),
+                level: "info".into(),
+            }
+        );
+        assert_eq!(
+            layered(vec!["myprog", "--format", "json", "--level", "debug"]),
+            Layers {
+                format: "json".into(),
+                level: "debug".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_occurrence_counts() {
+        let values = new_clap_config(vec!["myprog", "-v", "--format", "json"])
+            .with_occurrence_counts(true, "_count")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(values["format_count"], Value::new(None, 1));
+        assert!(!values.contains_key("verbosity_count"));
+    }
+
+    #[test]
+    fn test_variadic_positional() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Files {
+            output: String,
+            files: Vec<String>,
+        }
+
+        let app = App::new("app")
+            .arg(Arg::with_name("output").required(true))
+            .arg(Arg::with_name("files").multiple(true));
+        let mut conf = config::Config::new();
+        conf.merge(Clap::from_args(
+            app,
+            vec!["myprog", "out", "a.txt", "b.txt"],
+        ))
+        .unwrap();
+        assert_eq!(
+            conf.try_into::<Files>().unwrap(),
+            Files {
+                output: "out".into(),
+                files: vec!["a.txt".into(), "b.txt".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_subcommand() {
+        let mut conf = config::Config::new();
+        conf.merge(new_clap_config(vec!["myprog"]).default_subcommand("none"))
+            .unwrap();
+        assert_eq!(
+            conf.try_into::<Config>().unwrap(),
+            Config {
+                mode: Some("none".into()),
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_only_chosen_subcommand() {
+        let subcommand = |name| App::new(name).arg(Arg::with_name("flag").long("flag"));
+        let app = App::new("app")
+            .subcommand(subcommand("build"))
+            .subcommand(subcommand("test"))
+            .subcommand(subcommand("run"));
+        let values = Clap::from_args(app, vec!["myprog", "test", "--flag"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values.len(), 1);
+        assert_eq!(
+            values["test"].clone().into_table().unwrap()["flag"],
+            Value::new(None, true)
+        );
+    }
+
+    #[test]
+    fn test_ignore_conditional_defaults() {
+        let app = App::new("app")
+            .arg(Arg::with_name("verbose").long("verbose"))
+            .arg(
+                Arg::with_name("level")
+                    .long("level")
+                    .takes_value(true)
+                    .default_value_if("verbose", None, "debug"),
+            );
+
+        let values = Clap::from_args(app.clone(), vec!["myprog", "--verbose"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["level"], Value::new(None, "debug"));
+
+        let values = Clap::from_args(app, vec!["myprog", "--verbose"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("level"));
+    }
+
+    #[test]
+    fn test_get() {
+        let app = new_app().arg(Arg::with_name("port").long("port").takes_value(true));
+        let clap = Clap::from_args(app, vec!["myprog", "--port", "8080", "--format", "json"]);
+
+        assert_eq!(clap.get::<i32>("port"), Some(8080));
+        assert_eq!(clap.get::<String>("format"), Some("json".to_owned()));
+        assert_eq!(clap.get::<i32>("format"), None);
+        assert_eq!(clap.get::<String>("verbosity"), None);
+    }
+
+    #[test]
+    fn test_arg_types() {
+        let clap = new_clap_config(vec!["myprog"]);
+
+        let mut names: Vec<&str> = clap.arg_types().map(|(name, _)| name).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["format", "subcommand", "verbosity"]);
+
+        let paths: Vec<String> = clap
+            .arg_types_recursive()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "format",
+                "subcommand",
+                "subcommand.flag",
+                "subcommand.ids",
+                "verbosity"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_value_delimiter() {
+        let app = App::new("app").arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(false),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "--tag", "a,b"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["tag"], Value::new(None, vec!["a,b"]));
+    }
+
+    #[test]
+    fn test_subcommand_field_case() {
+        let app = App::new("app").subcommand(App::new("run-server"));
+        let values = Clap::from_args(app, vec!["myprog", "run-server"])
+            .subcommand_field("mode")
+            .rename_keys(KeyCase::SnakeCase)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["mode"], Value::new(None, "run_server"));
+        assert!(values.contains_key("run_server"));
+    }
+
+    #[test]
+    fn test_post_process() {
+        let values = new_clap_config(vec!["myprog", "-f", "json"])
+            .post_process(|values| {
+                values.insert("source".to_owned(), Value::new(None, "cli"));
+            })
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(values["source"], Value::new(None, "cli"));
+    }
+
+    #[test]
+    fn test_collect_once_per_merge() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let collected = Arc::new(AtomicUsize::new(0));
+        let counter = collected.clone();
+        let clap = new_clap_config(vec!["myprog", "-f", "json"])
+            .post_process(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .memoize(true);
+
+        let mut conf = config::Config::new();
+        conf.merge(clap)
+            .unwrap()
+            .merge(config::File::from_str(
+                "verbosity = 1",
+                config::FileFormat::Toml,
+            ))
+            .unwrap()
+            .merge(config::Environment::with_prefix("CLAP_CONFIG_TEST"))
+            .unwrap();
+
+        assert_eq!(conf.get_str("format").unwrap(), "json");
+        assert_eq!(collected.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_precompute() {
+        let args = vec!["myprog", "-vv", "--format=json", "subcommand", "-i1", "-i2"];
+        let lazy = new_clap_config(args.clone()).collect().unwrap();
+        let precomputed = new_clap_config(args).precompute();
+
+        assert_eq!(precomputed.collect().unwrap(), lazy);
+        assert_eq!(precomputed.collect().unwrap(), lazy);
+    }
+
+    #[test]
+    fn test_trim_values() {
+        let app = || App::new("app").arg(Arg::with_name("name").long("name").takes_value(true));
+
+        let values = Clap::from_args(app(), vec!["myprog", "--name", " foo "])
+            .trim_values(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["name"], Value::new(None, "foo"));
+
+        let values = Clap::from_args(app(), vec!["myprog", "--name", "  "])
+            .drop_empty(true)
+            .trim_values(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("name"));
+
+        let values = Clap::from_args(app(), vec!["myprog", "--name", "  "])
+            .trim_values(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["name"], Value::new(None, ""));
+    }
+
+    #[test]
+    fn test_drop_empty() {
+        let app = App::new("app")
+            .arg(Arg::with_name("name").long("name").takes_value(true))
+            .arg(
+                Arg::with_name("tag")
+                    .long("tag")
+                    .takes_value(true)
+                    .multiple(true),
+            );
+        let args = vec!["myprog", "--name", "", "--tag", "a", "--tag", ""];
+
+        let values = Clap::from_args(app.clone(), args.clone())
+            .collect()
+            .unwrap();
+        assert_eq!(values["name"], Value::new(None, ""));
+        assert_eq!(values["tag"], Value::new(None, vec!["a", ""]));
+
+        let values = Clap::from_args(app, args)
+            .drop_empty(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("name"));
+        assert_eq!(values["tag"], Value::new(None, vec!["a"]));
+    }
+
+    #[test]
+    fn test_app_name() {
+        let clap = Clap::from_args(App::new("app").bin_name("app-bin"), vec!["myprog"]);
+        assert_eq!(clap.app_name(), Some("app"));
+        assert_eq!(clap.bin_name(), Some("app-bin"));
+        let clap = Clap::from_args(App::new("app"), vec!["/usr/bin/myprog"]);
+        assert_eq!(clap.bin_name(), Some("myprog"));
+
+        assert_eq!(
+            Clap::from_parts(HashMap::new(), Default::default()).app_name(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_weak() {
+        let app = App::new("app")
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .arg(
+                Arg::with_name("fallback")
+                    .long("fallback")
+                    .takes_value(true),
+            )
+            .arg(Arg::with_name("other").long("other").takes_value(true));
+        let args = vec![
+            "myprog",
+            "--format",
+            "cli",
+            "--fallback",
+            "cli",
+            "--other",
+            "cli",
+        ];
+        let (weak, strong) = Clap::from_args(app, args).weak(&["fallback", "other"]);
+
+        let mut conf = config::Config::new();
+        conf.merge(weak)
+            .unwrap()
+            .merge(config::File::from_str(
+                "format = \"file\"\nfallback = \"file\"\n",
+                config::FileFormat::Toml,
+            ))
+            .unwrap()
+            .merge(strong)
+            .unwrap();
+
+        assert_eq!(conf.get_str("format").unwrap(), "cli");
+        assert_eq!(conf.get_str("fallback").unwrap(), "file");
+        assert_eq!(conf.get_str("other").unwrap(), "cli");
+    }
+
+    #[test]
+    fn test_memoize_clone() {
+        let app = App::new("app")
+            .arg(Arg::with_name("x").long("x").takes_value(true))
+            .arg(Arg::with_name("z").long("z").takes_value(true));
+        let clap = Clap::from_args(app, vec!["myprog", "--x", "1", "--z", "2"]).memoize(true);
+        assert_eq!(clap.collect().unwrap().len(), 2);
+
+        let keys: Vec<String> = clap
+            .clone()
+            .exclude(&["x"])
+            .collect()
+            .unwrap()
+            .into_keys()
+            .collect();
+        assert_eq!(keys, vec!["z"]);
+    }
+
+    #[test]
+    fn test_precompute_merge_into() {
+        let app = App::new("app").arg(
+            Arg::with_name("tags")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        );
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(
+            "tags = [\"a\"]",
+            config::FileFormat::Toml,
+        ))
+        .unwrap();
+        Clap::from_args(app, vec!["myprog", "--tag", "b"])
+            .append_multiples(&["tags"])
+            .precompute()
+            .merge_into(&mut conf)
+            .unwrap();
+
+        assert_eq!(conf.get::<Vec<String>>("tags").unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_weak_memoized() {
+        let app = App::new("app")
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .arg(
+                Arg::with_name("fallback")
+                    .long("fallback")
+                    .takes_value(true),
+            );
+        let args = vec!["myprog", "--format", "cli", "--fallback", "cli"];
+        let (weak, strong) = Clap::from_args(app, args).memoize(true).weak(&["fallback"]);
+
+        let weak: Vec<String> = weak.collect().unwrap().into_keys().collect();
+        let strong: Vec<String> = strong.collect().unwrap().into_keys().collect();
+        assert_eq!(weak, vec!["fallback"]);
+        assert_eq!(strong, vec!["format"]);
+    }
+
+    #[test]
+    fn test_map_value() {
+        fn bytes(size: &str) -> Value {
+            let (number, unit) = size.split_at(size.len() - 1);
+            match (number.parse::<i64>(), unit) {
+                (Ok(number), "k") => Value::new(None, number * 1024),
+                (Ok(number), "m") => Value::new(None, number * 1024 * 1024),
+                _ => Value::new(None, size),
+            }
+        }
+
+        let app = App::new("app")
+            .arg(Arg::with_name("size").long("size").takes_value(true))
+            .arg(
+                Arg::with_name("limits")
+                    .long("limit")
+                    .takes_value(true)
+                    .multiple(true),
+            );
+        let values = Clap::from_args(
+            app,
+            vec!["myprog", "--size", "1k", "--limit", "2m", "--limit", "3k"],
+        )
+        .map_value("size", bytes)
+        .map_value("limits", bytes)
+        .collect()
+        .unwrap();
+
+        assert_eq!(values["size"], Value::new(None, 1024));
+        assert_eq!(
+            values["limits"],
+            Value::new(None, vec![2 * 1024 * 1024, 3 * 1024])
+        );
+    }
+
+    #[test]
+    fn test_skip_hidden() {
+        let app = App::new("app")
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .arg(Arg::with_name("debug").long("debug").hidden(true));
+        let args = vec!["myprog", "--format", "json", "--debug"];
+
+        let values = Clap::from_args(app.clone(), args.clone())
+            .collect()
+            .unwrap();
+        assert_eq!(values["debug"], Value::new(None, true));
+
+        let values = Clap::from_args(app, args)
+            .skip_hidden(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("debug"));
+        assert_eq!(values["format"], Value::new(None, "json"));
+    }
+
+    #[test]
+    fn test_strict_keys() {
+        let app = App::new("app")
+            .arg(Arg::with_name("x-a").long("x-a").takes_value(true))
+            .arg(Arg::with_name("x-b").long("x-b").takes_value(true));
+        let clap = Clap::from_args(app, vec!["myprog", "--x-a", "1", "--x-b", "2"])
+            .map_key(|name| Some(name[..1].to_owned()));
+
+        assert_eq!(clap.collect().unwrap().len(), 1);
+
+        let error = clap.strict_keys(true).collect().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "args `x-a` and `x-b` are both collected as `x`"
+        );
+    }
+
+    #[test]
+    fn test_strict_keys_include_only() {
+        let app = App::new("app")
+            .arg(Arg::with_name("x-a").long("x-a").takes_value(true))
+            .arg(Arg::with_name("x-b").long("x-b").takes_value(true));
+        let values = Clap::from_args(app, vec!["myprog", "--x-a", "1", "--x-b", "2"])
+            .map_key(|name| Some(name[..1].to_owned()))
+            .include_only(&["x-a"])
+            .strict_keys(true)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["x"], Value::new(None, "1"));
+    }
+
+    #[test]
+    fn test_subcommand_alias() {
+        let app = App::new("app").subcommand(
+            App::new("subcommand")
+                .alias("sub")
+                .arg(Arg::with_name("flag").long("flag")),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "sub", "--flag"])
+            .subcommand_field("mode")
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["mode"], Value::new(None, "subcommand"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert_eq!(subcommand["flag"], Value::new(None, true));
+    }
+
+    #[test]
+    fn test_combine() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Combined {
+            format: String,
+            port: u16,
+        }
+
+        let first =
+            App::new("first").arg(Arg::with_name("format").long("format").takes_value(true));
+        let second = App::new("second").arg(Arg::with_name("port").long("port").takes_value(true));
+        let clap = Clap::combine(vec![
+            Clap::from_args(first, vec!["myprog", "--format", "json"]),
+            Clap::from_args(second, vec!["myprog", "--port", "8080"]),
+        ]);
+
+        let mut conf = config::Config::new();
+        conf.merge(clap).unwrap();
+        assert_eq!(
+            conf.try_into::<Combined>().unwrap(),
+            Combined {
+                format: "json".into(),
+                port: 8080
+            }
+        );
+    }
+
+    #[test]
+    fn test_combine_helpers() {
+        let first = App::new("first")
+            .arg(Arg::with_name("x").long("x").takes_value(true))
+            .arg(Arg::with_name("input"));
+        let second = App::new("second").arg(
+            Arg::with_name("y")
+                .long("y")
+                .takes_value(true)
+                .help("The y"),
+        );
+        let clap = Clap::combine(vec![
+            Clap::from_args(first, vec!["myprog", "--x", "1", "in"]),
+            Clap::from_args(second, vec!["myprog", "--y", "2"]),
+        ]);
+
+        assert_eq!(clap.get::<u32>("x"), Some(1));
+        assert_eq!(clap.get::<u32>("y"), Some(2));
+        let mut keys: Vec<String> = clap.known_keys().into_iter().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["input", "x", "y"]);
+        assert_eq!(clap.help_for("y"), Some("The y"));
+        assert_eq!(clap.arg_types().count(), 3);
+        assert_eq!(clap.to_argv(), vec!["--x=1", "in", "--y=2"]);
+        assert_eq!(
+            clap.positionals_in_order(),
+            vec![("input".to_owned(), Value::new(None, "in"))]
+        );
+    }
+
+    #[test]
+    fn test_combine_strict_keys() {
+        let app = || App::new("app").arg(Arg::with_name("format").long("format").takes_value(true));
+        let clap = Clap::combine(vec![
+            Clap::from_args(app(), vec!["myprog", "--format", "json"]),
+            Clap::from_args(app(), vec!["myprog", "--format", "yaml"]),
+        ]);
+
+        assert_eq!(clap.collect().unwrap()["format"], Value::new(None, "yaml"));
+        assert!(clap.strict_keys(true).collect().is_err());
+    }
+
+    #[test]
+    fn test_sort_multiple() {
+        let app = App::new("app").arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true),
+        );
+        let values = Clap::from_args(
+            app.clone(),
+            vec!["myprog", "--tag", "c", "--tag", "a", "--tag", "b"],
+        )
+        .sort_multiple(&["tag"])
+        .collect()
+        .unwrap();
+        assert_eq!(values["tag"], Value::new(None, vec!["a", "b", "c"]));
+
+        let values = Clap::from_args(
+            app,
+            vec!["myprog", "--tag", "10", "--tag", "9", "--tag", "1.5"],
+        )
+        .sort_multiple(&["tag"])
+        .coerce_numbers(true)
+        .collect()
+        .unwrap();
+        assert_eq!(values["tag"], Value::new(None, vec![1.5, 9.0, 10.0]));
+    }
+
+    #[test]
+    fn test_rename() {
+        let values = new_clap_config(vec!["myprog", "-v", "--format=json"])
+            .rename("format", "output_format")
+            .rename("verbosity", "level")
+            .collect()
+            .unwrap();
+
+        assert!(!values.contains_key("format"));
+        assert_eq!(values["output_format"], Value::new(None, "json"));
+        assert_eq!(values["level"], Value::new(None, 1));
+    }
+
+    #[test]
+    fn test_bool_as_string() {
+        let app = App::new("app")
+            .arg(Arg::with_name("cache").long("cache"))
+            .arg(Arg::with_name("color").long("color"));
+
+        let values = Clap::from_args(app.clone(), vec!["myprog", "--cache"])
+            .bool_as_string("cache", "enabled", None)
+            .bool_as_string("color", "always", Some("never"))
+            .collect()
+            .unwrap();
+        assert_eq!(values["cache"], Value::new(None, "enabled"));
+        assert_eq!(values["color"], Value::new(None, "never"));
+
+        let values = Clap::from_args(app, vec!["myprog"])
+            .bool_as_string("cache", "enabled", None)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("cache"));
+    }
+
+    #[test]
+    fn test_known_keys() {
+        let clap = new_clap_config(vec!["myprog"]);
+        let expected = [
+            "format",
+            "verbosity",
+            "subcommand.flag",
+            "subcommand.ids",
+            "mode",
+        ];
+        assert_eq!(
+            clap.known_keys(),
+            expected.iter().map(|key| (*key).to_owned()).collect()
+        );
+
+        let keys = clap.key_prefix("app").exclude(&["format"]).known_keys();
+        assert!(keys.contains("app.subcommand.ids"));
+        assert!(keys.contains("app.mode"));
+        assert!(!keys.contains("app.format"));
+    }
+
+    #[test]
+    fn test_emit_null_for() {
+        let app = || {
+            App::new("app")
+                .arg(Arg::with_name("desc").long("desc").takes_value(true))
+                .arg(Arg::with_name("name").long("name").takes_value(true))
+        };
+
+        let values = Clap::from_args(app(), vec!["myprog"])
+            .emit_null_for(&["desc"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["desc"], Value::new(None, None::<String>));
+        assert!(!values.contains_key("name"));
+
+        let mut conf = config::Config::new();
+        conf.merge(Clap::from_args(app(), vec!["myprog"]).emit_null_for(&["desc"]))
+            .unwrap();
+        assert_eq!(
+            conf.collect().unwrap()["desc"],
+            Value::new(None, None::<String>)
+        );
+    }
+
+    #[test]
+    fn test_collect_as_map() {
+        let app = App::new("app").arg(
+            Arg::with_name("define")
+                .short("D")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "-D", "a=1", "-D", "b=2", "-D", "c"])
+            .collect_as_map("define", '=')
+            .coerce_numbers(true)
+            .collect()
+            .unwrap();
+
+        let define = values["define"].clone().into_table().unwrap();
+        assert_eq!(define.len(), 2);
+        assert_eq!(define["a"], Value::new(None, 1));
+        assert_eq!(define["b"], Value::new(None, 2));
+    }
+
+    #[test]
+    fn test_skip_if_value() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Server {
+            port: u16,
+            hosts: Vec<String>,
+        }
+
+        let app = App::new("app")
+            .arg(Arg::with_name("port").long("port").takes_value(true))
+            .arg(
+                Arg::with_name("hosts")
+                    .long("host")
+                    .takes_value(true)
+                    .multiple(true),
+            );
+        let clap = Clap::from_args(
+            app,
+            vec!["myprog", "--port", "0", "--host", "a", "--host", "-"],
+        )
+        .skip_if_value("port", "0")
+        .skip_if_value("hosts", "-");
+
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(
+            "port = 8080\nhosts = []\n",
+            config::FileFormat::Toml,
+        ))
+        .unwrap()
+        .merge(clap)
+        .unwrap();
+        assert_eq!(
+            conf.try_into::<Server>().unwrap(),
+            Server {
+                port: 8080,
+                hosts: vec!["a".into()]
+            }
+        );
+    }
+
+    #[test]
+    fn test_collect_flat() {
+        let flat = new_clap_config(vec![
+            "myprog",
+            "-vv",
+            "subcommand",
+            "-i1",
+            "-i2",
+            "-i3",
+            "-F",
+        ])
+        .collect_flat()
+        .unwrap();
+
+        let expected: HashMap<String, String> = vec![
+            ("verbosity", "2"),
+            ("mode", "subcommand"),
+            ("subcommand.ids", "1,2,3"),
+            ("subcommand.flag", "true"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
+        assert_eq!(flat, expected);
+    }
+
+    #[test]
+    fn test_collect_flat_tuples_and_nulls() {
+        let app = App::new("app")
+            .arg(
+                Arg::with_name("point")
+                    .long("point")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(2),
+            )
+            .arg(Arg::with_name("name").long("name").takes_value(true));
+        let flat = Clap::from_args(
+            app,
+            vec!["myprog", "--point", "1", "2", "--point", "3", "4"],
+        )
+        .collect_as_tuples("point", 2)
+        .emit_null_for(&["name"])
+        .collect_flat()
+        .unwrap();
+
+        let expected: HashMap<String, String> =
+            vec![("point.0", "1,2"), ("point.1", "3,4"), ("name", "")]
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect();
+        assert_eq!(flat, expected);
+    }
+
+    #[test]
+    fn test_global_args() {
+        let app = App::new("app")
+            .arg(Arg::with_name("verbose").long("verbose").global(true))
+            .arg(
+                Arg::with_name("level")
+                    .long("level")
+                    .takes_value(true)
+                    .global(true),
+            )
+            .subcommand(App::new("subcommand"));
+        let values = Clap::from_args(
+            app,
+            vec!["myprog", "subcommand", "--verbose", "--level", "debug"],
+        )
+        .collect()
+        .unwrap();
+
+        assert_eq!(values["verbose"], Value::new(None, true));
+        assert_eq!(values["level"], Value::new(None, "debug"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert!(subcommand.is_empty());
+    }
+
+    #[test]
+    fn test_subcommand_fields() {
+        let app = App::new("app").subcommand(
+            App::new("db").subcommand(
+                App::new("migrate")
+                    .subcommand(App::new("up"))
+                    .arg(Arg::with_name("dry-run").long("dry-run")),
+            ),
+        );
+        let values = Clap::from_args(app, vec!["myprog", "db", "migrate", "--dry-run", "up"])
+            .subcommand_fields(&["command", "action"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["command"], Value::new(None, "db"));
+        let db = values["db"].clone().into_table().unwrap();
+        assert_eq!(db["action"], Value::new(None, "migrate"));
+        let migrate = db["migrate"].clone().into_table().unwrap();
+        assert_eq!(migrate["dry-run"], Value::new(None, true));
+        assert_eq!(migrate["action"], Value::new(None, "up"));
+    }
+
+    #[test]
+    fn test_subcommand_fields_nested_keys() {
+        let app = App::new("app").subcommand(App::new("db").subcommand(App::new("migrate")));
+        let clap = Clap::from_args(app, vec!["myprog", "db", "migrate"]).nested_keys(true);
+
+        let values = clap
+            .clone()
+            .subcommand_field("meta.mode")
+            .collect()
+            .unwrap();
+        let meta = values["meta"].clone().into_table().unwrap();
+        assert_eq!(meta["mode"], Value::new(None, "db.migrate"));
+
+        let values = clap
+            .subcommand_fields(&["run.command", "run.action"])
+            .collect()
+            .unwrap();
+        let run = values["run"].clone().into_table().unwrap();
+        assert_eq!(run["command"], Value::new(None, "db"));
+        let db = values["db"].clone().into_table().unwrap();
+        let run = db["run"].clone().into_table().unwrap();
+        assert_eq!(run["action"], Value::new(None, "migrate"));
+    }
+
+    #[test]
+    fn test_append_multiples() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Tags {
+            tags: Vec<String>,
+            subcommand: SubConfig,
+        }
+
+        let app = new_app().arg(
+            Arg::with_name("tags")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        );
+        let file = "tags = [\"a\"]\n[subcommand]\nids = [1]\n";
+        let args = vec!["myprog", "--tag", "b", "subcommand", "-i2", "-i3"];
+
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(file, config::FileFormat::Toml))
+            .unwrap();
+        Clap::from_args(app, args)
+            .append_multiples(&["tags", "ids"])
+            .merge_into(&mut conf)
+            .unwrap();
+
+        assert_eq!(
+            conf.try_into::<Tags>().unwrap(),
+            Tags {
+                tags: vec!["a".into(), "b".into()],
+                subcommand: SubConfig {
+                    ids: vec![1, 2, 3],
+                    flag: false
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_append_multiples_with_path_separator() {
+        let app = new_app().arg(
+            Arg::with_name("tags")
+                .long("tag")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        );
+        let file = "tags = [\"a\"]\n[subcommand]\nids = [1]\n";
+        let args = vec!["myprog", "--tag", "b", "subcommand", "-i2"];
+
+        let mut conf = config::Config::new();
+        conf.merge(config::File::from_str(file, config::FileFormat::Toml))
+            .unwrap();
+        Clap::from_args(app, args)
+            .path_separator("__")
+            .append_multiples(&["tags", "ids"])
+            .merge_into(&mut conf)
+            .unwrap();
+
+        assert_eq!(conf.get::<Vec<String>>("tags").unwrap(), vec!["a", "b"]);
+        assert_eq!(conf.get::<Vec<u32>>("subcommand.ids").unwrap(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_try_from_args_with_missing_keys() {
+        let app = new_app().arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .required(true),
+        );
+        let error = Clap::try_from_args_with(app, vec!["myprog", "subcommand"], |clap| {
+            clap.rename("output", "output_file").key_prefix("app")
+        })
+        .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "missing required config keys: app.output_file, app.subcommand.ids"
+        );
+    }
+
+    #[test]
+    fn test_coerce_multiple_floats_and_bools() {
+        let app = App::new("app")
+            .arg(
+                Arg::with_name("ratio")
+                    .long("ratio")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .arg(
+                Arg::with_name("flag")
+                    .long("flag")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1),
+            );
+        let values = Clap::from_args(
+            app,
+            vec![
+                "myprog", "--ratio", "1.5", "--ratio", "2", "--flag", "true", "--flag", "false",
+            ],
+        )
+        .coerce_numbers(true)
+        .coerce_bools(true)
+        .collect()
+        .unwrap();
+
+        assert_eq!(values["ratio"], Value::new(None, vec![1.5, 2.0]));
+        assert_eq!(values["flag"], Value::new(None, vec![true, false]));
+    }
+
+    #[test]
+    fn test_require_delimiter() {
+        let app = App::new("app")
+            .arg(
+                Arg::with_name("tags")
+                    .long("tag")
+                    .takes_value(true)
+                    .multiple(true)
+                    .require_delimiter(true),
+            )
+            .arg(Arg::with_name("input"));
+        let values = Clap::from_args(app, vec!["myprog", "--tag", "a,b", "input", "--tag", "c"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["tags"], Value::new(None, vec!["a", "b", "c"]));
+        assert_eq!(values["input"], Value::new(None, "input"));
+    }
+
+    #[test]
+    fn test_origin() {
+        let origin = "command line".to_owned();
+        let values = new_clap_config(vec!["myprog", "--format=json", "subcommand", "-i1"])
+            .origin(&origin)
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["format"], Value::new(Some(&origin), "json"));
+        let subcommand = values["subcommand"].clone().into_table().unwrap();
+        assert_eq!(
+            subcommand["ids"],
+            Value::new(Some(&origin), vec![Value::new(Some(&origin), "1")])
+        );
+
+        let mut conf = config::Config::new();
+        conf.merge(new_clap_config(vec!["myprog", "--format=json"]).origin(&origin))
+            .unwrap();
+        let error = conf.get::<i64>("format").unwrap_err();
+        assert!(error.to_string().ends_with("in command line"), "{}", error);
+    }
+
+    #[test]
+    fn test_subcommand_field_for() {
+        let app = || {
+            App::new("app")
+                .subcommand(App::new("db").subcommand(App::new("migrate")))
+                .subcommand(App::new("cache").subcommand(App::new("clear")))
+        };
+        let clap = |args| {
+            Clap::from_args(app(), args)
+                .subcommand_fields(&["command", "action"])
+                .subcommand_field_for("db", "db_action")
+        };
+
+        let values = clap(vec!["myprog", "db", "migrate"]).collect().unwrap();
+        assert_eq!(values["command"], Value::new(None, "db"));
+        let db = values["db"].clone().into_table().unwrap();
+        assert_eq!(db["db_action"], Value::new(None, "migrate"));
+        assert!(!db.contains_key("action"));
+
+        let values = clap(vec!["myprog", "cache", "clear"]).collect().unwrap();
+        let cache = values["cache"].clone().into_table().unwrap();
+        assert_eq!(cache["action"], Value::new(None, "clear"));
+    }
+
+    #[test]
+    fn test_collect_ref() {
+        for clap in [
+            new_clap_config(vec!["myprog", "-vv", "--format=json"]),
+            new_clap_config(vec!["myprog", "-vv", "--format=json"]).memoize(true),
+        ] {
+            let mut keys = Vec::new();
+            clap.collect_ref(|key, _| keys.push(key.to_owned()))
+                .unwrap();
+            keys.sort();
+            assert_eq!(keys, vec!["format", "verbosity"]);
+        }
+    }
+
+    #[test]
+    fn test_positionals_in_order() {
+        let app = App::new("app")
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .arg(Arg::with_name("source").index(1))
+            .arg(Arg::with_name("dest").index(2))
+            .arg(Arg::with_name("extra").index(3).multiple(true));
+        let clap = Clap::from_args(app, vec!["myprog", "a", "--format=json", "b", "c", "d"]);
+
+        assert_eq!(
+            clap.positionals_in_order(),
+            vec![
+                ("source".to_owned(), Value::new(None, "a")),
+                ("dest".to_owned(), Value::new(None, "b")),
+                ("extra".to_owned(), Value::new(None, vec!["c", "d"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_argv() {
+        let args = vec![
+            "myprog",
+            "-vvv",
+            "--format",
+            "json",
+            "input",
+            "subcommand",
+            "-i",
+            "1",
+            "-i",
+            "2",
+            "--flag",
+        ];
+        let new_app = || new_app().arg(Arg::with_name("input").index(1));
+        let clap = Clap::from_args(new_app(), args);
+        let argv = clap.to_argv();
+
+        assert_eq!(
+            argv,
+            vec![
+                "--format=json",
+                "-vvv",
+                "input",
+                "subcommand",
+                "--flag",
+                "--id=1",
+                "--id=2",
+            ]
+        );
+        let reparsed = Clap::from_args(new_app(), std::iter::once("myprog".to_owned()).chain(argv));
+        assert_eq!(reparsed.collect().unwrap(), clap.collect().unwrap());
+    }
+
+    #[test]
+    fn test_to_argv_multiple_values() {
+        let new_app = || {
+            App::new("app")
+                .arg(
+                    Arg::with_name("ids")
+                        .long("ids")
+                        .takes_value(true)
+                        .use_delimiter(true),
+                )
+                .arg(
+                    Arg::with_name("point")
+                        .long("point")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(2),
+                )
+        };
+        let args = vec![
+            "myprog", "--ids", "1,2,3", "--point", "1", "2", "--point", "3", "4",
+        ];
+        let clap = Clap::from_args(new_app(), args);
+        let argv = clap.to_argv();
+
+        assert_eq!(
+            argv,
+            vec!["--ids=1,2,3", "--point", "1", "2", "--point", "3", "4"]
+        );
+        let reparsed =
+            Clap::try_from_args(new_app(), std::iter::once("myprog".to_owned()).chain(argv))
+                .unwrap();
+        assert_eq!(reparsed.collect().unwrap(), clap.collect().unwrap());
+    }
+
+    #[test]
+    fn test_coerce_numbers_beyond_i64() {
+        let app = App::new("app")
+            .arg(Arg::with_name("offset").long("offset").takes_value(true))
+            .arg(
+                Arg::with_name("ids")
+                    .long("ids")
+                    .takes_value(true)
+                    .use_delimiter(true),
+            );
+        let clap = Clap::from_args(
+            app,
+            vec![
+                "myprog",
+                "--offset",
+                "18446744073709551615",
+                "--ids=1,18446744073709551615",
+            ],
+        )
+        .coerce_numbers(true);
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["offset"], Value::new(None, "18446744073709551615"));
+        assert_eq!(
+            values["ids"],
+            Value::new(None, vec!["1", "18446744073709551615"])
+        );
+        assert_eq!(
+            values["offset"].clone().into_str().unwrap().parse::<u64>(),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_trailing_key() {
+        let app = App::new("app")
+            .setting(clap::AppSettings::TrailingVarArg)
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .arg(Arg::with_name("input").index(1))
+            .arg(Arg::with_name("rest").index(2).multiple(true));
+        let clap = Clap::from_args(
+            app,
+            vec!["myprog", "--format", "json", "in", "--", "a", "b", "c"],
+        )
+        .trailing_key("command");
+
+        let values = clap.collect().unwrap();
+        assert_eq!(values["command"], Value::new(None, vec!["a", "b", "c"]));
+        assert_eq!(values["input"], Value::new(None, "in"));
+        assert!(!values.contains_key("rest"));
+    }
+
+    #[test]
+    fn test_with_app_help() {
+        let clap = Clap::with_app(new_app(), vec!["myprog", "--format", "json"]);
+
+        let help = clap.help().unwrap();
+        assert!(help.starts_with("app"));
+        assert!(help.contains("--format <format>"));
+        assert!(help.contains("subcommand"));
+        assert_eq!(clap.collect().unwrap()["format"], Value::new(None, "json"));
+        assert_eq!(Clap::from_args(new_app(), vec!["myprog"]).help(), None);
+    }
+
+    #[test]
+    fn test_subcommands_negate_reqs() {
+        let new_app = || {
+            new_app()
+                .setting(clap::AppSettings::SubcommandsNegateReqs)
+                .arg(
+                    Arg::with_name("input")
+                        .long("input")
+                        .takes_value(true)
+                        .required(true)
+                        .default_value("-"),
+                )
+        };
+
+        let values = Clap::from_args(new_app(), vec!["myprog", "subcommand", "-i", "1"])
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("input"));
+        assert!(values.contains_key("subcommand"));
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["input"], Value::new(None, "-"));
+
+        let values = Clap::from_args(
+            new_app(),
+            vec!["myprog", "--input=in", "subcommand", "-i", "1"],
+        )
+        .collect()
+        .unwrap();
+        assert_eq!(values["input"], Value::new(None, "in"));
+    }
+
+    #[test]
+    fn test_bin_name_field() {
+        let values = Clap::from_args(new_app(), vec!["./target/debug/myprog", "--format", "json"])
+            .bin_name_field("program")
+            .collect()
+            .unwrap();
+        assert_eq!(values["program"], Value::new(None, "myprog"));
+        assert_eq!(values["format"], Value::new(None, "json"));
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("program"));
+    }
+
+    #[test]
+    fn test_count_to_levels() {
+        let levels = ["warn", "info", "debug", "trace"];
+        let collect = |args: Vec<&str>| {
+            Clap::from_args(new_app(), args)
+                .count_to_levels("verbosity", &levels)
+                .collect()
+                .unwrap()
+        };
+
+        assert_eq!(
+            collect(vec!["myprog", "-vv"])["verbosity"],
+            Value::new(None, "debug")
+        );
+        assert_eq!(
+            collect(vec!["myprog", "-vvvvv"])["verbosity"],
+            Value::new(None, "trace")
+        );
+        assert!(!collect(vec!["myprog"]).contains_key("verbosity"));
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .count_to_levels("verbosity", &levels)
+            .user_only()
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("verbosity"));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let clap = new_clap_config(vec!["myprog", "-vv", "subcommand", "-i", "1", "-i", "2"]);
+
+        assert_eq!(
+            clap.deserialize::<Config>().unwrap(),
+            Config {
+                verbosity: 2,
+                mode: Some("subcommand".to_owned()),
+                subcommand: Some(SubConfig {
+                    ids: vec![1, 2],
+                    flag: false,
+                }),
+                ..Config::default()
+            }
+        );
+        assert_eq!(
+            clap.key_prefix("cli")
+                .deserialize::<HashMap<String, Config>>()
+                .unwrap()["cli"]
+                .verbosity,
+            2
+        );
+    }
+
+    #[test]
+    fn test_collect_as_tuples() {
+        let app = App::new("app").arg(
+            Arg::with_name("point")
+                .long("point")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(2),
+        );
+        let values = Clap::from_args(
+            app,
+            vec!["myprog", "--point", "1", "2", "--point", "3", "4"],
+        )
+        .collect_as_tuples("point", 2)
+        .coerce_numbers(true)
+        .collect()
+        .unwrap();
+
+        assert_eq!(
+            values["point"],
+            Value::new(
+                None,
+                vec![Value::new(None, vec![1, 2]), Value::new(None, vec![3, 4])]
+            )
+        );
+    }
+
+    #[test]
+    fn test_emit_all_booleans() {
+        let app = App::new("app")
+            .arg(Arg::with_name("dry-run").long("dry-run"))
+            .arg(Arg::with_name("force").long("force"));
+        let values = Clap::from_args(app.clone(), vec!["myprog", "--force"])
+            .emit_all_booleans(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["dry-run"], Value::new(None, false));
+        assert_eq!(values["force"], Value::new(None, true));
+
+        let values = Clap::from_args(app, vec!["myprog", "--force"])
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("dry-run"));
+    }
+
+    #[test]
+    fn test_help_for() {
+        let app = App::new("app")
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .help("Output format"),
+            )
+            .arg(Arg::with_name("quiet").long("quiet"))
+            .subcommand(App::new("run").arg(Arg::with_name("jobs").help("Number of jobs")));
+        let clap = Clap::from_args(app, vec!["myprog"]).key_prefix("cli");
+
+        assert_eq!(clap.help_for("cli.format"), Some("Output format"));
+        assert_eq!(clap.help_for("cli.run.jobs"), Some("Number of jobs"));
+        assert_eq!(clap.help_for("cli.quiet"), None);
+        assert_eq!(clap.help_for("format"), None);
+    }
+
+    #[test]
+    fn test_diff_from_defaults() {
+        let app = App::new("app")
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .default_value("yaml"),
+            )
+            .arg(
+                Arg::with_name("level")
+                    .long("level")
+                    .takes_value(true)
+                    .default_value("info"),
+            )
+            .arg(
+                Arg::with_name("color")
+                    .long("color")
+                    .takes_value(true)
+                    .default_value("auto"),
+            )
+            .arg(Arg::with_name("force").long("force"));
+        let clap = Clap::from_args(
+            app,
+            vec!["myprog", "--format", "json", "--level", "info", "--force"],
+        );
+
+        let mut keys: Vec<_> = clap.diff_from_defaults().into_keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["force", "format"]);
+        assert_eq!(
+            clap.diff_from_defaults()["format"],
+            Value::new(None, "json")
+        );
+        assert_eq!(clap.collect().unwrap()["color"], Value::new(None, "auto"));
+    }
+
+    #[test]
+    fn test_case_insensitive_lookup() {
+        let clap = Clap::from_args(new_app(), vec!["myprog", "--format", "json"])
+            .rename("Verbosity", "verbose")
+            .case_insensitive_lookup(true);
+
+        assert_eq!(clap.get::<String>("Format"), Some("json".to_owned()));
+        assert!(clap.known_keys().contains("verbose"));
+        assert!(clap.known_keys().contains("format"));
+        assert!(clap.collect().unwrap().contains_key("format"));
+
+        let clap = Clap::from_args(new_app(), vec!["myprog", "--format", "json"]);
+        assert_eq!(clap.get::<String>("Format"), None);
+    }
+
+    #[test]
+    fn test_negatable() {
+        let new_app = || {
+            App::new("app")
+                .arg(Arg::with_name("color").long("color").multiple(true))
+                .arg(Arg::with_name("no-color").long("no-color").multiple(true))
+        };
+        let collect = |args: Vec<&str>| {
+            Clap::from_args(new_app(), args)
+                .negatable("color", "color", "no-color")
+                .collect()
+                .unwrap()
+        };
+
+        assert_eq!(
+            collect(vec!["myprog", "--color", "--no-color"]),
+            vec![("color".to_owned(), Value::new(None, false))]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            collect(vec!["myprog", "--no-color", "--color"])["color"],
+            Value::new(None, true)
+        );
+        assert_eq!(
+            collect(vec!["myprog", "--color", "--no-color", "--color"])["color"],
+            Value::new(None, true)
+        );
+        assert!(collect(vec!["myprog"]).is_empty());
+
+        let values = Clap::from_args(new_app(), vec!["myprog", "--no-color"])
+            .negatable("ui.color", "color", "no-color")
+            .nested_keys(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values.len(), 1);
+        let ui = values["ui"].clone().into_table().unwrap();
+        assert_eq!(ui["color"], Value::new(None, false));
+    }
+
+    #[test]
+    fn test_alias_key() {
+        let values = Clap::from_args(new_app(), vec!["myprog", "--format", "json", "-v"])
+            .alias_key("format", &["output_format", "legacy.format"])
+            .collect()
+            .unwrap();
+
+        assert_eq!(values["format"], Value::new(None, "json"));
+        assert_eq!(values["output_format"], Value::new(None, "json"));
+        assert_eq!(values["legacy.format"], Value::new(None, "json"));
+        assert_eq!(values.len(), 4);
+    }
+
+    #[test]
+    fn test_strict_keys_renamed_conflict() {
+        let app = App::new("app")
+            .arg(Arg::with_name("quiet").long("quiet"))
+            .arg(Arg::with_name("verbose").long("verbose"));
+        let collect = |args: Vec<&str>| {
+            Clap::from_args(app.clone(), args)
+                .bool_as_string("quiet", "error", None)
+                .bool_as_string("verbose", "debug", None)
+                .rename("quiet", "log_level")
+                .rename("verbose", "log_level")
+                .strict_keys(true)
+                .collect()
+        };
+
+        assert_eq!(
+            collect(vec!["myprog", "--quiet", "--verbose"])
+                .unwrap_err()
+                .to_string(),
+            "args `quiet` and `verbose` are both collected as `log_level`"
+        );
+        assert_eq!(
+            collect(vec!["myprog", "--verbose"]).unwrap()["log_level"],
+            Value::new(None, "debug")
+        );
+        assert!(collect(vec!["myprog"]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_multiple_default_values() {
+        // clap 2 has no `default_values`, a delimited default gives several values.
+        let new_app = || {
+            App::new("app").arg(
+                Arg::with_name("tags")
+                    .long("tag")
+                    .takes_value(true)
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .default_value("a,b"),
+            )
+        };
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .collect()
+            .unwrap();
+        assert_eq!(values["tags"], Value::new(None, vec!["a", "b"]));
+
+        let values = Clap::from_args(new_app(), vec!["myprog"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert!(!values.contains_key("tags"));
+
+        let values = Clap::from_args(new_app(), vec!["myprog", "--tag", "c"])
+            .ignore_defaults(true)
+            .collect()
+            .unwrap();
+        assert_eq!(values["tags"], Value::new(None, vec!["c"]));
+    }
+
+    #[test]
+    fn test_as_path() {
+        let home = env::var("HOME").unwrap();
+        let collect = |path: &str, opts: PathOpts| {
+            let app =
+                App::new("app").arg(Arg::with_name("config").long("config").takes_value(true));
+            Clap::from_args(app, vec!["myprog", "--config", path])
+                .as_path("config", opts)
+                .collect()
+                .unwrap()
+                .remove("config")
+                .unwrap()
+        };
+        let expand = PathOpts {
+            expand_tilde: true,
+            ..PathOpts::default()
+        };
+
+        assert_eq!(
+            collect("~/x", expand),
+            Value::new(None, format!("{}/x", home))
+        );
+        assert_eq!(collect("~", expand), Value::new(None, home.clone()));
+        assert_eq!(collect("~x/y", expand), Value::new(None, "~x/y"));
+        assert_eq!(collect("~/x", PathOpts::default()), Value::new(None, "~/x"));
+
+        let canonicalize = PathOpts {
+            expand_tilde: true,
+            canonicalize: true,
+        };
+        assert_eq!(
+            collect("~/clap-config-missing/x", canonicalize),
+            Value::new(None, format!("{}/clap-config-missing/x", home))
+        );
+        let current = env::current_dir().unwrap().canonicalize().unwrap();
+        assert_eq!(
+            collect(".", canonicalize),
+            Value::new(None, current.to_string_lossy().into_owned())
+        );
+    }
+
+    #[test]
+    fn test_is_help_request() {
+        let new_app = || new_app().setting(clap::AppSettings::ArgRequiredElseHelp);
+        let error = Clap::try_from_args(new_app(), vec!["myprog"]).unwrap_err();
+        assert!(Clap::is_help_request(&error));
+
+        let error = Clap::try_from_args(new_app(), vec!["myprog", "--help"]).unwrap_err();
+        assert!(Clap::is_help_request(&error));
+
+        let error = Clap::try_from_args(new_app(), vec!["myprog", "--bogus"]).unwrap_err();
+        assert!(!Clap::is_help_request(&error));
+    }
+
+    #[test]
+    fn test_apply_to() {
+        #[derive(Debug, Deserialize, serde_derive::Serialize, PartialEq)]
+        struct Settings {
+            format: String,
+            verbosity: u8,
+            name: String,
+        }
+
+        let base = Settings {
+            format: "text".to_owned(),
+            verbosity: 0,
+            name: "base".to_owned(),
+        };
+        let clap = Clap::from_args(new_app(), vec!["myprog", "--format", "json", "-v"]);
+
+        assert_eq!(
+            clap.apply_to(base).unwrap(),
+            Settings {
+                format: "json".to_owned(),
+                verbosity: 1,
+                name: "base".to_owned(),
+            }
         );
     }
 }
@@ -0,0 +1,110 @@
+use crate::Clap;
+use config::{ConfigError, Source, Value};
+use indexmap::IndexMap;
+use std::collections::HashMap;
+
+impl Clap {
+    /// Like `collect`, but with the top level keys in the order their args are declared:
+    /// options and flags first, then positionals and subcommands. Other keys, e.g. the
+    /// `subcommand_field`, come last sorted by name, so dumps are deterministic.
+    ///
+    /// Tables inside, such as subcommand args, are config values and keep no order.
+    pub fn collect_ordered(&self) -> Result<IndexMap<String, Value>, ConfigError> {
+        let order: HashMap<String, usize> = self
+            .info
+            .iter()
+            .filter_map(|(name, info)| {
+                let key = self.key(name, Some(info))?;
+                let key = match &self.key_prefix {
+                    Some(prefix) => format!("{}{}{}", prefix, self.path_separator, key),
+                    None => key,
+                };
+                let key = match key.split_once(self.path_separator.as_str()) {
+                    Some((head, _)) if self.nested_keys => head.to_owned(),
+                    _ => key,
+                };
+                Some((key, info.order))
+            })
+            .collect();
+
+        let mut values: Vec<(String, Value)> = self.collect()?.into_iter().collect();
+        values.sort_by(|(a, _), (b, _)| {
+            let rank = |key: &String| order.get(key).copied().unwrap_or(usize::MAX);
+            (rank(a), a).cmp(&(rank(b), b))
+        });
+        Ok(values.into_iter().collect())
+    }
+}
+
+#[cfg(all(test, feature = "clap2"))]
+mod tests {
+    use crate::Clap;
+    use clap::{App, Arg};
+
+    #[test]
+    fn test_collect_ordered() {
+        let app = App::new("app")
+            .arg(Arg::with_name("zone").long("zone").takes_value(true))
+            .arg(Arg::with_name("verbose").long("verbose"))
+            .arg(Arg::with_name("address").long("address").takes_value(true))
+            .arg(Arg::with_name("input"))
+            .subcommand(App::new("build").arg(Arg::with_name("release").long("release")));
+        let clap = Clap::from_args(
+            app,
+            vec![
+                "myprog",
+                "--address=::1",
+                "--verbose",
+                "in",
+                "--zone=eu",
+                "build",
+                "--release",
+            ],
+        )
+        .subcommand_field("command");
+
+        let keys: Vec<String> = clap.collect_ordered().unwrap().into_keys().collect();
+        assert_eq!(
+            keys,
+            vec!["zone", "verbose", "address", "input", "build", "command"]
+        );
+    }
+}
+
+#[cfg(all(test, feature = "clap4"))]
+mod clap4_tests {
+    use crate::Clap;
+    use clap4::{Arg, ArgAction, Command};
+
+    #[test]
+    fn test_collect_ordered() {
+        let app = Command::new("app")
+            .arg(Arg::new("zone").long("zone"))
+            .arg(Arg::new("input"))
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(Arg::new("address").long("address"))
+            .subcommand(Command::new("build"));
+        let clap = Clap::from_args(
+            app,
+            vec![
+                "myprog",
+                "--address=::1",
+                "--verbose",
+                "in",
+                "--zone=eu",
+                "build",
+            ],
+        )
+        .subcommand_field("command");
+
+        let keys: Vec<String> = clap.collect_ordered().unwrap().into_keys().collect();
+        assert_eq!(
+            keys,
+            vec!["zone", "verbose", "address", "input", "build", "command"]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::Clap;
+use config::ConfigError;
+
+impl Clap {
+    /// Render the collected values as pretty printed JSON, e.g. to show the effective
+    /// command line config.
+    #[cfg(feature = "json")]
+    pub fn to_json_string(&self) -> Result<String, ConfigError> {
+        let value = self.deserialize::<serde_json::Value>()?;
+        serde_json::to_string_pretty(&value).map_err(|error| ConfigError::Foreign(Box::new(error)))
+    }
+
+    /// Render the collected values as TOML, e.g. to show the effective command line config.
+    ///
+    /// TOML has no null, so this fails for values collected with `emit_null_for`.
+    #[cfg(feature = "toml")]
+    pub fn to_toml_string(&self) -> Result<String, ConfigError> {
+        let value = self.deserialize::<toml::Value>()?;
+        toml::to_string(&value).map_err(|error| ConfigError::Foreign(Box::new(error)))
+    }
+}
+
+#[cfg(all(test, feature = "clap2"))]
+mod tests {
+    use crate::Clap;
+    use clap::{App, Arg};
+
+    fn new_clap() -> Clap {
+        let app = App::new("app")
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .arg(Arg::with_name("verbosity").short("v").multiple(true))
+            .subcommand(App::new("subcommand").arg(Arg::with_name("flag").long("flag")));
+        Clap::from_args(
+            app,
+            vec!["myprog", "-vv", "--format=json", "subcommand", "--flag"],
+        )
+        .subcommand_field("mode")
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_to_json_string() {
+        assert_eq!(
+            new_clap().to_json_string().unwrap(),
+            r#"{
+  "format": "json",
+  "mode": "subcommand",
+  "subcommand": {
+    "flag": true
+  },
+  "verbosity": 2
+}"#
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_to_toml_string() {
+        assert_eq!(
+            new_clap().to_toml_string().unwrap(),
+            "format = \"json\"\nmode = \"subcommand\"\nverbosity = 2\n\n[subcommand]\nflag = true\n"
+        );
+    }
+}
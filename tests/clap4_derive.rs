@@ -0,0 +1,74 @@
+#![cfg(feature = "clap4")]
+
+extern crate clap4 as clap;
+
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_config::Clap;
+use serde_derive::Deserialize;
+
+#[derive(Debug, Parser)]
+#[command(name = "app")]
+struct Args {
+    #[arg(short, long)]
+    format: Option<String>,
+    #[arg(short, long = "verbose", action = ArgAction::Count)]
+    verbosity: u8,
+    #[command(subcommand)]
+    subcommand: Option<Commands>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    Subcommand {
+        #[arg(short = 'F', long)]
+        flag: bool,
+        #[arg(short, long = "id", required = true)]
+        ids: Vec<u32>,
+    },
+}
+
+#[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+#[serde(default)]
+struct Config {
+    format: Option<String>,
+    verbosity: usize,
+    subcommand: Option<SubConfig>,
+    mode: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default, Eq, PartialEq)]
+#[serde(default)]
+struct SubConfig {
+    ids: Vec<u32>,
+    flag: bool,
+}
+
+#[test]
+fn test_derive() {
+    let matches = Args::command().get_matches_from(vec![
+        "myprog",
+        "-vv",
+        "--format=json",
+        "subcommand",
+        "-F",
+        "-i1",
+        "-i2",
+    ]);
+    assert!(Args::from_arg_matches(&matches).is_ok());
+
+    let mut conf = config::Config::new();
+    conf.merge(Clap::from_arg_matches(&Args::command(), matches).subcommand_field("mode"))
+        .unwrap();
+    assert_eq!(
+        conf.try_into::<Config>().unwrap(),
+        Config {
+            format: Some("json".into()),
+            verbosity: 2,
+            subcommand: Some(SubConfig {
+                ids: vec![1, 2],
+                flag: true,
+            }),
+            mode: Some("subcommand".into()),
+        }
+    );
+}